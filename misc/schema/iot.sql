@@ -51,6 +51,7 @@ CREATE TABLE IF NOT EXISTS `user` (
   `phone_number` VARCHAR(25) NOT NULL,
   `salt` TEXT NULL,
   `verifier` TEXT NULL,
+  `srp_group` VARCHAR(20) NULL DEFAULT 'LEGACY_1024',
   `language_id` INT NOT NULL,
   `created_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP(),
   PRIMARY KEY (`id`),
@@ -82,6 +83,257 @@ ENGINE = InnoDB
 DEFAULT CHARACTER SET = utf8mb4;
 
 
+-- -----------------------------------------------------
+-- Table `password_reset`
+-- -----------------------------------------------------
+CREATE TABLE IF NOT EXISTS `password_reset` (
+  `id` BIGINT UNSIGNED NOT NULL AUTO_INCREMENT,
+  `user_id` INT UNSIGNED NOT NULL,
+  `token_hash` CHAR(64) NOT NULL,
+  `expires_at` BIGINT NOT NULL,
+  `used_at` BIGINT NULL,
+  `created_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP(),
+  PRIMARY KEY (`id`),
+  UNIQUE INDEX `password_reset_token_hash_UNIQUE` (`token_hash` ASC) VISIBLE,
+  INDEX `fk_password_reset_user_id_idx` (`user_id` ASC) VISIBLE,
+  CONSTRAINT `fk_password_reset_user_id`
+    FOREIGN KEY (`user_id`)
+    REFERENCES `user` (`id`)
+    ON DELETE CASCADE
+    ON UPDATE NO ACTION)
+ENGINE = InnoDB
+DEFAULT CHARACTER SET = utf8mb4;
+
+
+-- -----------------------------------------------------
+-- Table `subject_revocation`
+-- -----------------------------------------------------
+CREATE TABLE IF NOT EXISTS `subject_revocation` (
+  `subject` VARCHAR(255) NOT NULL,
+  `revoked_before` BIGINT NOT NULL,
+  PRIMARY KEY (`subject`))
+ENGINE = InnoDB
+DEFAULT CHARACTER SET = utf8mb4;
+
+
+-- -----------------------------------------------------
+-- Table `token_revocation`
+-- -----------------------------------------------------
+CREATE TABLE IF NOT EXISTS `token_revocation` (
+  `jwt_id` VARCHAR(36) NOT NULL,
+  `expires_at` BIGINT NOT NULL,
+  `created_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP(),
+  PRIMARY KEY (`jwt_id`),
+  INDEX `token_revocation_expires_at_idx` (`expires_at` ASC) VISIBLE)
+ENGINE = InnoDB
+DEFAULT CHARACTER SET = utf8mb4;
+
+
+-- -----------------------------------------------------
+-- Table `srp_session`
+-- -----------------------------------------------------
+CREATE TABLE IF NOT EXISTS `srp_session` (
+  `id` VARCHAR(36) NOT NULL,
+  `identity` VARCHAR(45) NOT NULL,
+  `handshake` TEXT NOT NULL,
+  `expires_at` BIGINT NOT NULL,
+  `created_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP(),
+  PRIMARY KEY (`id`),
+  INDEX `srp_session_expires_at_idx` (`expires_at` ASC) VISIBLE)
+ENGINE = InnoDB
+DEFAULT CHARACTER SET = utf8mb4;
+
+
+-- -----------------------------------------------------
+-- Table `refresh_token`
+-- -----------------------------------------------------
+CREATE TABLE IF NOT EXISTS `refresh_token` (
+  `id` BIGINT UNSIGNED NOT NULL AUTO_INCREMENT,
+  `family_id` VARCHAR(36) NOT NULL,
+  `family_issued_at` BIGINT NOT NULL,
+  `user_id` INT UNSIGNED NOT NULL,
+  `token_hash` CHAR(64) NOT NULL,
+  `session_type` VARCHAR(20) NOT NULL,
+  `scope` VARCHAR(1000) NOT NULL DEFAULT '',
+  `client_id` VARCHAR(64) NULL,
+  `expires_at` BIGINT NOT NULL,
+  `used_at` BIGINT NULL,
+  `revoked_at` BIGINT NULL,
+  `created_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP(),
+  PRIMARY KEY (`id`),
+  UNIQUE INDEX `refresh_token_token_hash_UNIQUE` (`token_hash` ASC) VISIBLE,
+  INDEX `refresh_token_family_id_idx` (`family_id` ASC) VISIBLE,
+  INDEX `fk_refresh_token_user_id_idx` (`user_id` ASC) VISIBLE,
+  CONSTRAINT `fk_refresh_token_user_id`
+    FOREIGN KEY (`user_id`)
+    REFERENCES `user` (`id`)
+    ON DELETE CASCADE
+    ON UPDATE NO ACTION)
+ENGINE = InnoDB
+DEFAULT CHARACTER SET = utf8mb4;
+
+
+-- -----------------------------------------------------
+-- Table `oauth_client`
+-- -----------------------------------------------------
+CREATE TABLE IF NOT EXISTS `oauth_client` (
+  `id` INT UNSIGNED NOT NULL AUTO_INCREMENT,
+  `client_id` VARCHAR(64) NOT NULL,
+  `secret_hash` CHAR(64) NULL,
+  `name` VARCHAR(100) NOT NULL,
+  `redirect_uris` VARCHAR(2000) NOT NULL DEFAULT '',
+  `allowed_scopes` VARCHAR(1000) NOT NULL DEFAULT '',
+  `created_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP(),
+  PRIMARY KEY (`id`),
+  UNIQUE INDEX `oauth_client_client_id_UNIQUE` (`client_id` ASC) VISIBLE)
+ENGINE = InnoDB
+DEFAULT CHARACTER SET = utf8mb4;
+
+
+-- -----------------------------------------------------
+-- Table `user_permission`
+-- -----------------------------------------------------
+CREATE TABLE IF NOT EXISTS `user_permission` (
+  `user_id` INT UNSIGNED NOT NULL,
+  `permission_id` INT NOT NULL,
+  PRIMARY KEY (`user_id`, `permission_id`),
+  INDEX `fk_user_permission_permission_id_idx` (`permission_id` ASC) VISIBLE,
+  CONSTRAINT `fk_user_permission_user_id`
+    FOREIGN KEY (`user_id`)
+    REFERENCES `user` (`id`)
+    ON DELETE CASCADE
+    ON UPDATE NO ACTION,
+  CONSTRAINT `fk_user_permission_permission_id`
+    FOREIGN KEY (`permission_id`)
+    REFERENCES `permission` (`id`)
+    ON DELETE CASCADE
+    ON UPDATE NO ACTION)
+ENGINE = InnoDB
+DEFAULT CHARACTER SET = utf8mb4;
+
+
+-- -----------------------------------------------------
+-- Table `authorization_code`
+-- -----------------------------------------------------
+CREATE TABLE IF NOT EXISTS `authorization_code` (
+  `id` BIGINT UNSIGNED NOT NULL AUTO_INCREMENT,
+  `code_hash` CHAR(64) NOT NULL,
+  `client_id` VARCHAR(64) NOT NULL,
+  `user_id` INT UNSIGNED NOT NULL,
+  `redirect_uri` VARCHAR(2000) NOT NULL,
+  `scope` VARCHAR(1000) NOT NULL,
+  `code_challenge` VARCHAR(128) NOT NULL,
+  `nonce` VARCHAR(255) NULL,
+  `auth_time` BIGINT NOT NULL,
+  `expires_at` BIGINT NOT NULL,
+  `used_at` BIGINT NULL,
+  `created_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP(),
+  PRIMARY KEY (`id`),
+  UNIQUE INDEX `authorization_code_code_hash_UNIQUE` (`code_hash` ASC) VISIBLE,
+  INDEX `fk_authorization_code_user_id_idx` (`user_id` ASC) VISIBLE,
+  INDEX `fk_authorization_code_client_id_idx` (`client_id` ASC) VISIBLE,
+  CONSTRAINT `fk_authorization_code_user_id`
+    FOREIGN KEY (`user_id`)
+    REFERENCES `user` (`id`)
+    ON DELETE CASCADE
+    ON UPDATE NO ACTION,
+  CONSTRAINT `fk_authorization_code_client_id`
+    FOREIGN KEY (`client_id`)
+    REFERENCES `oauth_client` (`client_id`)
+    ON DELETE CASCADE
+    ON UPDATE NO ACTION)
+ENGINE = InnoDB
+DEFAULT CHARACTER SET = utf8mb4;
+
+
+-- -----------------------------------------------------
+-- Table `device_authorization`
+-- -----------------------------------------------------
+CREATE TABLE IF NOT EXISTS `device_authorization` (
+  `id` BIGINT UNSIGNED NOT NULL AUTO_INCREMENT,
+  `device_code_hash` CHAR(64) NOT NULL,
+  `user_code` CHAR(8) NOT NULL,
+  `client_id` VARCHAR(64) NOT NULL,
+  `scope` VARCHAR(1000) NOT NULL,
+  `user_id` INT UNSIGNED NULL,
+  `approved_at` BIGINT NULL,
+  `denied_at` BIGINT NULL,
+  `poll_interval` INT NOT NULL,
+  `last_polled_at` BIGINT NULL,
+  `expires_at` BIGINT NOT NULL,
+  `used_at` BIGINT NULL,
+  `created_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP(),
+  PRIMARY KEY (`id`),
+  UNIQUE INDEX `device_authorization_device_code_hash_UNIQUE` (`device_code_hash` ASC) VISIBLE,
+  UNIQUE INDEX `device_authorization_user_code_UNIQUE` (`user_code` ASC) VISIBLE,
+  INDEX `fk_device_authorization_user_id_idx` (`user_id` ASC) VISIBLE,
+  INDEX `fk_device_authorization_client_id_idx` (`client_id` ASC) VISIBLE,
+  CONSTRAINT `fk_device_authorization_user_id`
+    FOREIGN KEY (`user_id`)
+    REFERENCES `user` (`id`)
+    ON DELETE CASCADE
+    ON UPDATE NO ACTION,
+  CONSTRAINT `fk_device_authorization_client_id`
+    FOREIGN KEY (`client_id`)
+    REFERENCES `oauth_client` (`client_id`)
+    ON DELETE CASCADE
+    ON UPDATE NO ACTION)
+ENGINE = InnoDB
+DEFAULT CHARACTER SET = utf8mb4;
+
+
+-- -----------------------------------------------------
+-- Table `login_state`
+-- -----------------------------------------------------
+CREATE TABLE IF NOT EXISTS `login_state` (
+  `id` BIGINT UNSIGNED NOT NULL AUTO_INCREMENT,
+  `state_hash` CHAR(64) NOT NULL,
+  `browser_hash` CHAR(64) NOT NULL,
+  `provider` VARCHAR(64) NOT NULL,
+  `code_verifier` VARCHAR(128) NOT NULL,
+  `nonce` VARCHAR(255) NULL,
+  `scope` VARCHAR(1000) NOT NULL,
+  `pending_authorization` TEXT NULL,
+  `link_user_id` INT UNSIGNED NULL,
+  `expires_at` BIGINT NOT NULL,
+  `used_at` BIGINT NULL,
+  `created_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP(),
+  PRIMARY KEY (`id`),
+  UNIQUE INDEX `login_state_state_hash_UNIQUE` (`state_hash` ASC) VISIBLE,
+  INDEX `fk_login_state_link_user_id_idx` (`link_user_id` ASC) VISIBLE,
+  CONSTRAINT `fk_login_state_link_user_id`
+    FOREIGN KEY (`link_user_id`)
+    REFERENCES `user` (`id`)
+    ON DELETE CASCADE
+    ON UPDATE NO ACTION)
+ENGINE = InnoDB
+DEFAULT CHARACTER SET = utf8mb4;
+
+
+-- -----------------------------------------------------
+-- Table `external_identity`
+-- -----------------------------------------------------
+CREATE TABLE IF NOT EXISTS `external_identity` (
+  `id` BIGINT UNSIGNED NOT NULL AUTO_INCREMENT,
+  `user_id` INT UNSIGNED NOT NULL,
+  `provider` VARCHAR(64) NOT NULL,
+  `subject` VARCHAR(255) NOT NULL,
+  `email` VARCHAR(255) NOT NULL,
+  `access_token` TEXT NULL,
+  `created_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP(),
+  `updated_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP() ON UPDATE CURRENT_TIMESTAMP(),
+  PRIMARY KEY (`id`),
+  UNIQUE INDEX `external_identity_provider_subject_UNIQUE` (`provider` ASC, `subject` ASC) VISIBLE,
+  UNIQUE INDEX `external_identity_user_id_provider_UNIQUE` (`user_id` ASC, `provider` ASC) VISIBLE,
+  CONSTRAINT `fk_external_identity_user_id`
+    FOREIGN KEY (`user_id`)
+    REFERENCES `user` (`id`)
+    ON DELETE CASCADE
+    ON UPDATE NO ACTION)
+ENGINE = InnoDB
+DEFAULT CHARACTER SET = utf8mb4;
+
+
 SET SQL_MODE=@OLD_SQL_MODE;
 SET FOREIGN_KEY_CHECKS=@OLD_FOREIGN_KEY_CHECKS;
 SET UNIQUE_CHECKS=@OLD_UNIQUE_CHECKS;
@@ -0,0 +1,96 @@
+use log::error;
+use sqlx::{Done, Error, MySqlPool, Row};
+use sqlx::mysql::MySqlDone;
+
+use crate::entities::authorization_code_entity::AuthorizationCodeEntity;
+
+pub struct AuthorizationCodeDao<'a> {
+    conn: &'a MySqlPool
+}
+
+impl <'a> AuthorizationCodeDao<'a> {
+    pub fn new(conn: &'a MySqlPool) -> Self {
+        AuthorizationCodeDao {
+            conn
+        }
+    }
+
+    pub async fn insert_one(&mut self, code: &AuthorizationCodeEntity) -> bool {
+        let done: Result<MySqlDone, Error> = sqlx::query("INSERT INTO authorization_code(code_hash, client_id, user_id, redirect_uri, scope, code_challenge, nonce, auth_time, expires_at) VALUES(?,?,?,?,?,?,?,?,?)")
+            .bind(&code.code_hash)
+            .bind(&code.client_id)
+            .bind(code.user_id)
+            .bind(&code.redirect_uri)
+            .bind(&code.scope)
+            .bind(&code.code_challenge)
+            .bind(&code.nonce)
+            .bind(code.auth_time)
+            .bind(code.expires_at).execute(self.conn).await;
+
+        match done {
+            Ok(d) => d.rows_affected() == 1,
+            Err(err) => {
+                error!("error storing authorization code {}", err);
+                false
+            }
+        }
+    }
+
+    pub async fn find_by_hash(&mut self, code_hash: &String) -> Option<AuthorizationCodeEntity> {
+        let row = sqlx::query("SELECT authorization_code.*, user.email FROM authorization_code JOIN user ON user.id = authorization_code.user_id WHERE code_hash = ?")
+            .bind(code_hash)
+            .fetch_one(self.conn).await;
+
+        match row {
+            Ok(r) => {
+                Some(AuthorizationCodeEntity {
+                    id: Some(r.get_unchecked("id")),
+                    code_hash: r.get("code_hash"),
+                    client_id: r.get("client_id"),
+                    user_id: r.get_unchecked("user_id"),
+                    email: Some(r.get("email")),
+                    redirect_uri: r.get("redirect_uri"),
+                    scope: r.get("scope"),
+                    code_challenge: r.get("code_challenge"),
+                    nonce: r.get("nonce"),
+                    auth_time: r.get("auth_time"),
+                    expires_at: r.get("expires_at"),
+                    used_at: r.get("used_at"),
+                })
+            }
+            Err(Error::RowNotFound) => None,
+            Err(err) => {
+                error!("error reading authorization code {}", err);
+                None
+            }
+        }
+    }
+
+    /// set used_at of an unused code, see consume_once
+    pub async fn mark_used(&mut self, id: u64, now: i64) -> bool {
+        let done: Result<MySqlDone, Error> = sqlx::query("UPDATE authorization_code SET used_at = ? WHERE id = ? AND used_at IS NULL")
+            .bind(now)
+            .bind(id).execute(self.conn).await;
+
+        match done {
+            Ok(d) => d.rows_affected() == 1,
+            Err(err) => {
+                error!("error redeeming authorization code {}", err);
+                false
+            }
+        }
+    }
+
+    pub async fn delete_expired(&mut self, now: i64) -> u64 {
+        let done: Result<MySqlDone, Error> = sqlx::query("DELETE FROM authorization_code WHERE expires_at <= ?")
+            .bind(now).execute(self.conn).await;
+
+        match done {
+            Ok(d) => d.rows_affected(),
+            Err(err) => {
+                error!("error deleting expired authorization codes {}", err);
+                0
+            }
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use log::error;
+use sqlx::{Done, Error, MySqlPool, Row};
+use sqlx::mysql::{MySqlDone, MySqlRow};
+
+use crate::entities::device_authorization_entity::DeviceAuthorizationEntity;
+
+pub struct DeviceAuthorizationDao<'a> {
+    conn: &'a MySqlPool
+}
+
+impl <'a> DeviceAuthorizationDao<'a> {
+    pub fn new(conn: &'a MySqlPool) -> Self {
+        DeviceAuthorizationDao {
+            conn
+        }
+    }
+
+    pub async fn insert_one(&mut self, grant: &DeviceAuthorizationEntity) -> bool {
+        let done: Result<MySqlDone, Error> = sqlx::query("INSERT INTO device_authorization(device_code_hash, user_code, client_id, scope, poll_interval, expires_at) VALUES(?,?,?,?,?,?)")
+            .bind(&grant.device_code_hash)
+            .bind(&grant.user_code)
+            .bind(&grant.client_id)
+            .bind(&grant.scope)
+            .bind(grant.poll_interval)
+            .bind(grant.expires_at).execute(self.conn).await;
+
+        match done {
+            Ok(d) => d.rows_affected() == 1,
+            Err(err) => {
+                error!("error storing device authorization {}", err);
+                false
+            }
+        }
+    }
+
+    pub async fn find_by_device_code_hash(&mut self, device_code_hash: &String) -> Option<DeviceAuthorizationEntity> {
+        let row = sqlx::query("SELECT device_authorization.*, user.email FROM device_authorization LEFT JOIN user ON user.id = device_authorization.user_id WHERE device_code_hash = ?")
+            .bind(device_code_hash)
+            .fetch_one(self.conn).await;
+
+        match row {
+            Ok(r) => Some(to_entity(&r)),
+            Err(Error::RowNotFound) => None,
+            Err(err) => {
+                error!("error reading device authorization {}", err);
+                None
+            }
+        }
+    }
+
+    pub async fn find_by_user_code(&mut self, user_code: &String) -> Option<DeviceAuthorizationEntity> {
+        let row = sqlx::query("SELECT device_authorization.*, user.email FROM device_authorization LEFT JOIN user ON user.id = device_authorization.user_id WHERE user_code = ?")
+            .bind(user_code)
+            .fetch_one(self.conn).await;
+
+        match row {
+            Ok(r) => Some(to_entity(&r)),
+            Err(Error::RowNotFound) => None,
+            Err(err) => {
+                error!("error reading device authorization {}", err);
+                None
+            }
+        }
+    }
+
+    /// answer a grant nobody has answered yet, false when it was answered since it was read
+    pub async fn answer(&mut self, id: u64, user_id: u32, approve: bool, now: i64) -> bool {
+        let statement = match approve {
+            true => "UPDATE device_authorization SET user_id = ?, approved_at = ? WHERE id = ? AND approved_at IS NULL AND denied_at IS NULL AND expires_at > ?",
+            false => "UPDATE device_authorization SET user_id = ?, denied_at = ? WHERE id = ? AND approved_at IS NULL AND denied_at IS NULL AND expires_at > ?"
+        };
+        let done: Result<MySqlDone, Error> = sqlx::query(statement)
+            .bind(user_id)
+            .bind(now)
+            .bind(id)
+            .bind(now).execute(self.conn).await;
+
+        match done {
+            Ok(d) => d.rows_affected() == 1,
+            Err(err) => {
+                error!("error answering device authorization {}", err);
+                false
+            }
+        }
+    }
+
+    pub async fn record_poll(&mut self, id: u64, poll_interval: i64, now: i64) -> bool {
+        let done: Result<MySqlDone, Error> = sqlx::query("UPDATE device_authorization SET last_polled_at = ?, poll_interval = ? WHERE id = ?")
+            .bind(now)
+            .bind(poll_interval)
+            .bind(id).execute(self.conn).await;
+
+        match done {
+            Ok(d) => d.rows_affected() == 1,
+            Err(err) => {
+                error!("error recording device poll {}", err);
+                false
+            }
+        }
+    }
+
+    /// set used_at of a grant exchanged for tokens, see consume_once
+    pub async fn mark_used(&mut self, id: u64, now: i64) -> bool {
+        let done: Result<MySqlDone, Error> = sqlx::query("UPDATE device_authorization SET used_at = ? WHERE id = ? AND used_at IS NULL")
+            .bind(now)
+            .bind(id).execute(self.conn).await;
+
+        match done {
+            Ok(d) => d.rows_affected() == 1,
+            Err(err) => {
+                error!("error redeeming device authorization {}", err);
+                false
+            }
+        }
+    }
+
+    /// delete the grants past their expiry, their user codes can be handed out again
+    pub async fn delete_expired(&mut self, now: i64) -> u64 {
+        let done: Result<MySqlDone, Error> = sqlx::query("DELETE FROM device_authorization WHERE expires_at <= ?")
+            .bind(now).execute(self.conn).await;
+
+        match done {
+            Ok(d) => d.rows_affected(),
+            Err(err) => {
+                error!("error deleting expired device authorizations {}", err);
+                0
+            }
+        }
+    }
+}
+
+fn to_entity(r: &MySqlRow) -> DeviceAuthorizationEntity {
+    DeviceAuthorizationEntity {
+        id: Some(r.get_unchecked("id")),
+        device_code_hash: r.get("device_code_hash"),
+        user_code: r.get("user_code"),
+        client_id: r.get("client_id"),
+        scope: r.get("scope"),
+        user_id: r.get_unchecked("user_id"),
+        email: r.get("email"),
+        approved_at: r.get("approved_at"),
+        denied_at: r.get("denied_at"),
+        poll_interval: r.get_unchecked::<i32, _>("poll_interval") as i64,
+        last_polled_at: r.get("last_polled_at"),
+        expires_at: r.get("expires_at"),
+        used_at: r.get("used_at"),
+    }
+}
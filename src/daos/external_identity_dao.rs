@@ -0,0 +1,106 @@
+use log::error;
+use sqlx::{Done, Error, MySqlPool, Row};
+use sqlx::mysql::{MySqlDone, MySqlRow};
+
+use crate::entities::external_identity_entity::ExternalIdentityEntity;
+
+pub struct ExternalIdentityDao<'a> {
+    conn: &'a MySqlPool
+}
+
+impl <'a> ExternalIdentityDao<'a> {
+    pub fn new(conn: &'a MySqlPool) -> Self {
+        ExternalIdentityDao {
+            conn
+        }
+    }
+
+    pub async fn find_by_subject(&mut self, provider: &str, subject: &String) -> Option<ExternalIdentityEntity> {
+        let row = sqlx::query("SELECT * FROM external_identity WHERE provider = ? AND subject = ?")
+            .bind(provider)
+            .bind(subject)
+            .fetch_one(self.conn).await;
+
+        match row {
+            Ok(r) => Some(to_entity(&r)),
+            Err(Error::RowNotFound) => None,
+            Err(err) => {
+                error!("error reading external identity {}", err);
+                None
+            }
+        }
+    }
+
+    pub async fn find_by_user_id(&mut self, user_id: u32) -> Vec<ExternalIdentityEntity> {
+        let rows = sqlx::query("SELECT * FROM external_identity WHERE user_id = ? ORDER BY id")
+            .bind(user_id)
+            .fetch_all(self.conn).await;
+
+        match rows {
+            Ok(rows) => rows.iter().map(to_entity).collect(),
+            Err(err) => {
+                error!("error reading external identities {}", err);
+                vec![]
+            }
+        }
+    }
+
+    /// fails when the provider account or the user's account of the provider is linked already
+    pub async fn insert_one(&mut self, identity: &ExternalIdentityEntity) -> bool {
+        let done: Result<MySqlDone, Error> = sqlx::query("INSERT INTO external_identity(user_id, provider, subject, email, access_token) VALUES(?,?,?,?,?)")
+            .bind(identity.user_id)
+            .bind(&identity.provider)
+            .bind(&identity.subject)
+            .bind(&identity.email)
+            .bind(&identity.access_token).execute(self.conn).await;
+
+        match done {
+            Ok(d) => d.rows_affected() == 1,
+            Err(err) => {
+                error!("error linking external identity {}", err);
+                false
+            }
+        }
+    }
+
+    /// keep the email and access token of the latest login
+    pub async fn update_login(&mut self, id: u64, email: &String, access_token: &Option<String>) -> bool {
+        let done: Result<MySqlDone, Error> = sqlx::query("UPDATE external_identity SET email = ?, access_token = ? WHERE id = ?")
+            .bind(email)
+            .bind(access_token)
+            .bind(id).execute(self.conn).await;
+
+        match done {
+            Ok(_) => true,
+            Err(err) => {
+                error!("error updating external identity {}", err);
+                false
+            }
+        }
+    }
+
+    pub async fn delete_one(&mut self, user_id: u32, provider: &str) -> bool {
+        let done: Result<MySqlDone, Error> = sqlx::query("DELETE FROM external_identity WHERE user_id = ? AND provider = ?")
+            .bind(user_id)
+            .bind(provider).execute(self.conn).await;
+
+        match done {
+            Ok(d) => d.rows_affected() == 1,
+            Err(err) => {
+                error!("error unlinking external identity {}", err);
+                false
+            }
+        }
+    }
+}
+
+fn to_entity(r: &MySqlRow) -> ExternalIdentityEntity {
+    ExternalIdentityEntity {
+        id: Some(r.get_unchecked("id")),
+        user_id: r.get_unchecked("user_id"),
+        provider: r.get("provider"),
+        subject: r.get("subject"),
+        email: r.get("email"),
+        access_token: r.get("access_token"),
+    }
+}
@@ -0,0 +1,98 @@
+use log::error;
+use sqlx::{Done, Error, MySqlPool, Row};
+use sqlx::mysql::MySqlDone;
+
+use crate::entities::login_state_entity::LoginStateEntity;
+
+pub struct LoginStateDao<'a> {
+    conn: &'a MySqlPool
+}
+
+impl <'a> LoginStateDao<'a> {
+    pub fn new(conn: &'a MySqlPool) -> Self {
+        LoginStateDao {
+            conn
+        }
+    }
+
+    pub async fn insert_one(&mut self, login: &LoginStateEntity) -> bool {
+        let pending_authorization = login.pending_authorization.as_ref()
+            .map(|authorization| serde_json::to_string(authorization).unwrap());
+        let done: Result<MySqlDone, Error> = sqlx::query("INSERT INTO login_state(state_hash, browser_hash, provider, code_verifier, nonce, scope, pending_authorization, link_user_id, expires_at) VALUES(?,?,?,?,?,?,?,?,?)")
+            .bind(&login.state_hash)
+            .bind(&login.browser_hash)
+            .bind(&login.provider)
+            .bind(&login.code_verifier)
+            .bind(&login.nonce)
+            .bind(&login.scope)
+            .bind(pending_authorization)
+            .bind(login.link_user_id)
+            .bind(login.expires_at).execute(self.conn).await;
+
+        match done {
+            Ok(d) => d.rows_affected() == 1,
+            Err(err) => {
+                error!("error storing login state {}", err);
+                false
+            }
+        }
+    }
+
+    pub async fn find_by_state_hash(&mut self, state_hash: &String) -> Option<LoginStateEntity> {
+        let row = sqlx::query("SELECT * FROM login_state WHERE state_hash = ?")
+            .bind(state_hash)
+            .fetch_one(self.conn).await;
+
+        match row {
+            Ok(r) => {
+                let pending_authorization: Option<String> = r.get("pending_authorization");
+                Some(LoginStateEntity {
+                    id: Some(r.get_unchecked("id")),
+                    state_hash: r.get("state_hash"),
+                    browser_hash: r.get("browser_hash"),
+                    provider: r.get("provider"),
+                    code_verifier: r.get("code_verifier"),
+                    nonce: r.get("nonce"),
+                    scope: r.get("scope"),
+                    pending_authorization: pending_authorization.and_then(|json| serde_json::from_str(&json).ok()),
+                    link_user_id: r.get_unchecked("link_user_id"),
+                    expires_at: r.get("expires_at"),
+                    used_at: r.get("used_at"),
+                })
+            }
+            Err(Error::RowNotFound) => None,
+            Err(err) => {
+                error!("error reading login state {}", err);
+                None
+            }
+        }
+    }
+
+    /// set used_at of an unused login state, see consume_once
+    pub async fn mark_used(&mut self, id: u64, now: i64) -> bool {
+        let done: Result<MySqlDone, Error> = sqlx::query("UPDATE login_state SET used_at = ? WHERE id = ? AND used_at IS NULL")
+            .bind(now)
+            .bind(id).execute(self.conn).await;
+
+        match done {
+            Ok(d) => d.rows_affected() == 1,
+            Err(err) => {
+                error!("error consuming login state {}", err);
+                false
+            }
+        }
+    }
+
+    pub async fn delete_expired(&mut self, now: i64) -> u64 {
+        let done: Result<MySqlDone, Error> = sqlx::query("DELETE FROM login_state WHERE expires_at <= ?")
+            .bind(now).execute(self.conn).await;
+
+        match done {
+            Ok(d) => d.rows_affected(),
+            Err(err) => {
+                error!("error deleting expired login states {}", err);
+                0
+            }
+        }
+    }
+}
@@ -1 +1,9 @@
 pub mod user_dao;
+pub mod password_reset_dao;
+pub mod refresh_token_dao;
+pub mod oauth_client_dao;
+pub mod permission_dao;
+pub mod authorization_code_dao;
+pub mod device_authorization_dao;
+pub mod login_state_dao;
+pub mod external_identity_dao;
@@ -0,0 +1,40 @@
+use log::error;
+use sqlx::{Error, MySqlPool, Row};
+
+use crate::entities::oauth_client_entity::OAuthClientEntity;
+
+pub struct OAuthClientDao<'a> {
+    conn: &'a MySqlPool
+}
+
+impl <'a> OAuthClientDao<'a> {
+    pub fn new(conn: &'a MySqlPool) -> Self {
+        OAuthClientDao {
+            conn
+        }
+    }
+
+    pub async fn find_by_client_id(&mut self, client_id: &String) -> Option<OAuthClientEntity> {
+        let row = sqlx::query("SELECT * FROM oauth_client WHERE client_id = ?")
+            .bind(client_id)
+            .fetch_one(self.conn).await;
+
+        match row {
+            Ok(r) => {
+                let redirect_uris: String = r.get("redirect_uris");
+                Some(OAuthClientEntity {
+                    client_id: r.get("client_id"),
+                    secret_hash: r.get("secret_hash"),
+                    name: r.get("name"),
+                    redirect_uris: redirect_uris.split_whitespace().map(|uri| uri.to_string()).collect(),
+                    allowed_scopes: r.get("allowed_scopes"),
+                })
+            }
+            Err(Error::RowNotFound) => None,
+            Err(err) => {
+                error!("error reading oauth client {}", err);
+                None
+            }
+        }
+    }
+}
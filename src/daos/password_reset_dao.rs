@@ -0,0 +1,60 @@
+use log::error;
+use sqlx::{Done, Error, MySqlPool};
+use sqlx::mysql::MySqlDone;
+
+pub struct PasswordResetDao<'a> {
+    conn: &'a MySqlPool
+}
+
+impl <'a> PasswordResetDao<'a> {
+    pub fn new(conn: &'a MySqlPool) -> Self {
+        PasswordResetDao {
+            conn
+        }
+    }
+
+    pub async fn insert_one(&mut self, user_id: u32, token_hash: &String, expires_at: i64) -> bool {
+        let done: Result<MySqlDone, Error> = sqlx::query("INSERT INTO password_reset(user_id, token_hash, expires_at) VALUES(?,?,?)")
+            .bind(user_id)
+            .bind(token_hash)
+            .bind(expires_at).execute(self.conn).await;
+
+        match done {
+            Ok(d) => d.rows_affected() == 1,
+            Err(err) => {
+                error!("error storing password reset {}", err);
+                false
+            }
+        }
+    }
+
+    /// mark an unexpired, unused token of the user as used, returns true when it was valid
+    pub async fn consume(&mut self, user_id: u32, token_hash: &String, now: i64) -> bool {
+        let done: Result<MySqlDone, Error> = sqlx::query("UPDATE password_reset SET used_at = ? WHERE token_hash = ? AND user_id = ? AND used_at IS NULL AND expires_at > ?")
+            .bind(now)
+            .bind(token_hash)
+            .bind(user_id)
+            .bind(now).execute(self.conn).await;
+
+        match done {
+            Ok(d) => d.rows_affected() == 1,
+            Err(err) => {
+                error!("error consuming password reset {}", err);
+                false
+            }
+        }
+    }
+
+    pub async fn delete_expired(&mut self, now: i64) -> u64 {
+        let done: Result<MySqlDone, Error> = sqlx::query("DELETE FROM password_reset WHERE expires_at <= ?")
+            .bind(now).execute(self.conn).await;
+
+        match done {
+            Ok(d) => d.rows_affected(),
+            Err(err) => {
+                error!("error deleting expired password resets {}", err);
+                0
+            }
+        }
+    }
+}
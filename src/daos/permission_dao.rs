@@ -0,0 +1,35 @@
+use log::error;
+use sqlx::{MySqlPool, Row};
+
+use crate::services::jwt_service::Permission;
+
+pub struct PermissionDao<'a> {
+    conn: &'a MySqlPool
+}
+
+impl <'a> PermissionDao<'a> {
+    pub fn new(conn: &'a MySqlPool) -> Self {
+        PermissionDao {
+            conn
+        }
+    }
+
+    /// permissions granted to the user, unknown names are skipped
+    pub async fn find_by_user_id(&mut self, user_id: u32) -> Vec<Permission> {
+        let rows = sqlx::query("SELECT permission.name FROM user_permission JOIN permission ON permission.id = user_permission.permission_id WHERE user_permission.user_id = ?")
+            .bind(user_id)
+            .fetch_all(self.conn).await;
+
+        match rows {
+            Ok(rows) => {
+                rows.iter()
+                    .filter_map(|r| r.get_unchecked::<String, _>("name").parse().ok())
+                    .collect()
+            }
+            Err(err) => {
+                error!("error reading permissions {}", err);
+                vec![]
+            }
+        }
+    }
+}
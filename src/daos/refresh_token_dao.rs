@@ -0,0 +1,126 @@
+use log::error;
+use sqlx::{Done, Error, MySqlPool, Row};
+use sqlx::mysql::MySqlDone;
+
+use crate::entities::refresh_token_entity::{RefreshTokenEntity, RefreshTokenFamily};
+
+pub struct RefreshTokenDao<'a> {
+    conn: &'a MySqlPool
+}
+
+impl <'a> RefreshTokenDao<'a> {
+    pub fn new(conn: &'a MySqlPool) -> Self {
+        RefreshTokenDao {
+            conn
+        }
+    }
+
+    pub async fn insert_one(&mut self, family: &RefreshTokenFamily, token_hash: &str, expires_at: i64) -> bool {
+        let done: Result<MySqlDone, Error> = sqlx::query("INSERT INTO refresh_token(family_id, family_issued_at, user_id, token_hash, session_type, scope, client_id, expires_at) VALUES(?,?,?,?,?,?,?,?)")
+            .bind(&family.family_id)
+            .bind(family.issued_at)
+            .bind(family.user_id)
+            .bind(token_hash)
+            .bind(family.session_type.to_string())
+            .bind(&family.scope)
+            .bind(&family.client_id)
+            .bind(expires_at).execute(self.conn).await;
+
+        match done {
+            Ok(d) => d.rows_affected() == 1,
+            Err(err) => {
+                error!("error storing refresh token {}", err);
+                false
+            }
+        }
+    }
+
+    pub async fn find_by_hash(&mut self, token_hash: &String) -> Option<RefreshTokenEntity> {
+        let row = sqlx::query("SELECT refresh_token.*, user.email FROM refresh_token JOIN user ON user.id = refresh_token.user_id WHERE token_hash = ?")
+            .bind(token_hash)
+            .fetch_one(self.conn).await;
+
+        match row {
+            Ok(r) => {
+                let session_type: String = r.get("session_type");
+                Some(RefreshTokenEntity {
+                    id: r.get_unchecked("id"),
+                    family_id: r.get("family_id"),
+                    family_issued_at: r.get("family_issued_at"),
+                    user_id: r.get_unchecked("user_id"),
+                    email: r.get("email"),
+                    session_type: session_type.parse().ok()?,
+                    scope: r.get("scope"),
+                    client_id: r.get("client_id"),
+                    expires_at: r.get("expires_at"),
+                    used_at: r.get("used_at"),
+                    revoked_at: r.get("revoked_at"),
+                })
+            }
+            Err(Error::RowNotFound) => None,
+            Err(err) => {
+                error!("error reading refresh token {}", err);
+                None
+            }
+        }
+    }
+
+    /// set used_at of a live token, see consume_once
+    pub async fn mark_used(&mut self, id: u64, now: i64) -> bool {
+        let done: Result<MySqlDone, Error> = sqlx::query("UPDATE refresh_token SET used_at = ? WHERE id = ? AND used_at IS NULL AND revoked_at IS NULL")
+            .bind(now)
+            .bind(id).execute(self.conn).await;
+
+        match done {
+            Ok(d) => d.rows_affected() == 1,
+            Err(err) => {
+                error!("error rotating refresh token {}", err);
+                false
+            }
+        }
+    }
+
+    /// revoke every token of the family that is not revoked yet
+    pub async fn revoke_family(&mut self, family_id: &String, now: i64) -> bool {
+        let done: Result<MySqlDone, Error> = sqlx::query("UPDATE refresh_token SET revoked_at = ? WHERE family_id = ? AND revoked_at IS NULL")
+            .bind(now)
+            .bind(family_id).execute(self.conn).await;
+
+        match done {
+            Ok(_) => true,
+            Err(err) => {
+                error!("error revoking refresh token family {}", err);
+                false
+            }
+        }
+    }
+
+    /// revoke every live token of the user, all of its logins included
+    pub async fn revoke_user(&mut self, user_id: u32, now: i64) -> bool {
+        let done: Result<MySqlDone, Error> = sqlx::query("UPDATE refresh_token SET revoked_at = ? WHERE user_id = ? AND revoked_at IS NULL")
+            .bind(now)
+            .bind(user_id).execute(self.conn).await;
+
+        match done {
+            Ok(_) => true,
+            Err(err) => {
+                error!("error revoking refresh tokens of user {}", err);
+                false
+            }
+        }
+    }
+
+    /// delete the tokens past their expiry, a replay of one is refused as expired before reuse is looked at
+    pub async fn delete_expired(&mut self, now: i64) -> u64 {
+        let done: Result<MySqlDone, Error> = sqlx::query("DELETE FROM refresh_token WHERE expires_at <= ?")
+            .bind(now).execute(self.conn).await;
+
+        match done {
+            Ok(d) => d.rows_affected(),
+            Err(err) => {
+                error!("error deleting expired refresh tokens {}", err);
+                0
+            }
+        }
+    }
+}
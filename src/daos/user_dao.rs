@@ -1,10 +1,12 @@
 
 use uuid::Uuid;
-use sqlx::{MySql, Error, Row, Executor, Pool, MySqlPool};
+use sqlx::{MySql, Error, Row, Executor, Pool, MySqlPool, Done};
 use sqlx::pool::PoolConnection;
 use sqlx::mysql::{MySqlRow, MySqlDone};
 use std::borrow::BorrowMut;
 use crate::entities::user_entity::UserEntity;
+use crate::entities::srp::srp_group::SrpGroup;
+use log::error;
 
 pub struct UserDao<'a> {
     conn: &'a MySqlPool
@@ -17,59 +19,116 @@ impl <'a> UserDao<'a> {
     }
 
     pub async fn find_by_email(&mut self, email: &String) -> Option<UserEntity> {
-        let row = sqlx::query("SELECT * from user where user.email like ?")
+        let row = sqlx::query("SELECT * from user where user.email = ?")
             .bind(email)
             .fetch_one(self.conn).await;
 
-        let mut f_name = None;
-        let mut l_name = None;
         match row {
-            Ok(r) => {
-                if let Ok(first_name) = r.try_get("first_name") {
-                    f_name = Some(first_name);
-                }
-
-                if let Ok(last_name) = r.try_get("last_name") {
-                    l_name = Some(last_name);
-                }
-                let user_entity = Some(UserEntity {
-                    id: r.get_unchecked("id"),
-                    email: r.get("email"),
-                    first_name: f_name,
-                    last_name: l_name,
-                    phone_number: r.get("phone_number"),
-                    language_id: r.get_unchecked("language_id"),
-                    salt: r.get("salt"),
-                    verifier: r.get("verifier")
-                });
-                user_entity
+            Ok(r) => Some(to_entity(&r)),
+            Err(Error::RowNotFound) => None,
+            Err(err) => {
+                error!("error reading user {}", err);
+                None
             }
+        }
+    }
+
+    pub async fn find_by_id(&mut self, id: u32) -> Option<UserEntity> {
+        let row = sqlx::query("SELECT * from user where user.id = ?")
+            .bind(id)
+            .fetch_one(self.conn).await;
+
+        match row {
+            Ok(r) => Some(to_entity(&r)),
+            Err(Error::RowNotFound) => None,
             Err(err) => {
-                println!("{:?}", err);
+                error!("error reading user {}", err);
                 None
             }
         }
     }
 
     pub async fn insert_one(&mut self, e: UserEntity) -> Option<UserEntity> {
-        let done: Result<MySqlDone, Error> = sqlx::query("INSERT INTO user(first_name, last_name, email, phone_number, language_id) VALUES(?,?,?,?,?)")
+        let done: Result<MySqlDone, Error> = sqlx::query("INSERT INTO user(first_name, last_name, email, phone_number, salt, verifier, srp_group, language_id) VALUES(?,?,?,?,?,?,?,?)")
             .bind(&e.first_name)
             .bind(&e.last_name)
             .bind(&e.email)
             .bind(&e.phone_number)
+            .bind(&e.salt)
+            .bind(&e.verifier)
+            .bind(e.srp_group.map(|group| group.to_string()))
             .bind(e.language_id).execute(self.conn).await;
-        /*let affect_rows = self.conn.affected_rows();
 
-        match affect_rows {
-            1 => {
-                self.conn.close(statement);
-                Some(self.find_by_email(&e.email).unwrap())
+        match done {
+            Ok(d) if d.rows_affected() == 1 => {
+                self.find_by_email(&e.email).await
             }
-            _ => {
+            Ok(_) => {
                 None
             }
-        }*/
-        None
+            Err(err) => {
+                error!("error storing user {}", err);
+                None
+            }
+        }
+    }
+
+    /// replace the srp credentials of a user, returns true when the row was updated
+    pub async fn update_credentials(&mut self, email: &String, salt: &String, verifier: &String, group: SrpGroup) -> bool {
+        let done: Result<MySqlDone, Error> = sqlx::query("UPDATE user SET salt = ?, verifier = ?, srp_group = ? WHERE email = ?")
+            .bind(salt)
+            .bind(verifier)
+            .bind(group.to_string())
+            .bind(email).execute(self.conn).await;
+
+        match done {
+            Ok(d) => d.rows_affected() == 1,
+            Err(err) => {
+                error!("error updating credentials {}", err);
+                false
+            }
+        }
+    }
+
+    pub async fn delete_one(&mut self, id: u32) -> bool {
+        let done: Result<MySqlDone, Error> = sqlx::query("DELETE FROM user WHERE id = ?")
+            .bind(id).execute(self.conn).await;
+
+        match done {
+            Ok(d) => d.rows_affected() == 1,
+            Err(err) => {
+                error!("error deleting user {}", err);
+                false
+            }
+        }
+    }
+}
+
+fn to_entity(r: &MySqlRow) -> UserEntity {
+    let mut f_name = None;
+    let mut l_name = None;
+    let mut group = None;
+    if let Ok(first_name) = r.try_get("first_name") {
+        f_name = Some(first_name);
+    }
+
+    if let Ok(last_name) = r.try_get("last_name") {
+        l_name = Some(last_name);
+    }
+
+    if let Ok(srp_group) = r.try_get::<String, _>("srp_group") {
+        group = srp_group.parse().ok();
+    }
+    UserEntity {
+        id: r.get_unchecked("id"),
+        email: r.get("email"),
+        first_name: f_name,
+        last_name: l_name,
+        phone_number: r.get("phone_number"),
+        language_id: r.get_unchecked("language_id"),
+        salt: r.get("salt"),
+        verifier: r.get("verifier"),
+        srp_group: group
     }
 }
 
@@ -0,0 +1,19 @@
+/// a one time authorization code, RFC 6749 4.1 bound to a PKCE challenge
+#[derive(Debug)]
+pub struct AuthorizationCodeEntity {
+    pub id: Option<u64>,
+    /// sha256 of the code, hex encoded
+    pub code_hash: String,
+    pub client_id: String,
+    pub user_id: u32,
+    /// filled from the user when read back
+    pub email: Option<String>,
+    pub redirect_uri: String,
+    pub scope: String,
+    /// base64url sha256 of the client's code_verifier
+    pub code_challenge: String,
+    pub nonce: Option<String>,
+    pub auth_time: i64,
+    pub expires_at: i64,
+    pub used_at: Option<i64>,
+}
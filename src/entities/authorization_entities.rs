@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+
+/// query of GET /oauth/authorize, RFC 6749 4.1.1 with the RFC 7636 PKCE parameters
+#[derive(Deserialize, Serialize, Debug)]
+pub struct AuthorizeQuery {
+    pub response_type: String,
+    pub client_id: String,
+    pub redirect_uri: String,
+    pub scope: Option<String>,
+    pub state: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+    pub nonce: Option<String>,
+    /// social login for users without a session, "facebook", "google" or a provider of oauth_providers.json
+    pub provider: Option<String>,
+}
+
+/// a validated authorization request waiting for the user, carried through the social login state
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct PendingAuthorization {
+    pub client_id: String,
+    pub redirect_uri: String,
+    pub scope: String,
+    pub state: Option<String>,
+    pub code_challenge: String,
+    pub nonce: Option<String>,
+}
+
+/// form body of POST /oauth/device_authorization, RFC 8628 3.1
+#[derive(Deserialize, Serialize, Debug)]
+pub struct DeviceAuthorizationRequest {
+    /// public clients name themselves here, confidential ones use basic credentials
+    pub client_id: Option<String>,
+    pub scope: Option<String>,
+}
+
+/// RFC 8628 3.2, the device shows the user_code and verification_uri and starts polling
+#[derive(Deserialize, Serialize, Debug)]
+pub struct DeviceAuthorizationResponse {
+    pub device_code: String,
+    pub user_code: String,
+    pub verification_uri: String,
+    pub verification_uri_complete: String,
+    pub expires_in: i64,
+    pub interval: i64,
+}
+
+/// body of POST /oauth/device, the signed in user answering the code shown on a device
+#[derive(Deserialize, Serialize, Debug)]
+pub struct DeviceApprovalRequest {
+    pub user_code: String,
+    #[serde(default = "default_approve")]
+    pub approve: bool,
+}
+
+fn default_approve() -> bool { true }
@@ -0,0 +1,22 @@
+/// a pending RFC 8628 device grant, the device polls with its device code
+/// while the user approves the user code from a device that has a browser
+#[derive(Debug)]
+pub struct DeviceAuthorizationEntity {
+    pub id: Option<u64>,
+    /// sha256 of the device code, hex encoded
+    pub device_code_hash: String,
+    /// stored without the dash it is shown with
+    pub user_code: String,
+    pub client_id: String,
+    pub scope: String,
+    /// the approving user, with the email filled when read back
+    pub user_id: Option<u32>,
+    pub email: Option<String>,
+    pub approved_at: Option<i64>,
+    pub denied_at: Option<i64>,
+    /// seconds the device has to wait between polls, raised on every slow_down
+    pub poll_interval: i64,
+    pub last_polled_at: Option<i64>,
+    pub expires_at: i64,
+    pub used_at: Option<i64>,
+}
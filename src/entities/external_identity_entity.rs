@@ -0,0 +1,18 @@
+use serde::Serialize;
+
+/// a provider account linked to a user, social logins find their user through it
+#[derive(Debug, Serialize)]
+pub struct ExternalIdentityEntity {
+    #[serde(skip_serializing)]
+    pub id: Option<u64>,
+    #[serde(skip_serializing)]
+    pub user_id: u32,
+    pub provider: String,
+    /// the provider's id of the account
+    pub subject: String,
+    /// the email the provider last reported, it may differ from the user's
+    pub email: String,
+    /// the provider's access token of the last login, never shown
+    #[serde(skip_serializing)]
+    pub access_token: Option<String>,
+}
@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+
+/// public half of a signing key in RFC 7517 form
+#[derive(Deserialize, Serialize, Debug)]
+pub struct Jwk {
+    pub kty: String,
+    pub kid: String,
+    pub alg: String,
+    #[serde(rename = "use")]
+    pub key_use: String,
+    /// RSA modulus and exponent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub e: Option<String>,
+    /// EC curve and point
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crv: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub x: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub y: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct JwkSet {
+    pub keys: Vec<Jwk>,
+}
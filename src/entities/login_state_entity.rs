@@ -0,0 +1,23 @@
+use crate::entities::authorization_entities::PendingAuthorization;
+
+/// a social login between the redirect to the provider and its callback, used once
+#[derive(Debug)]
+pub struct LoginStateEntity {
+    pub id: Option<u64>,
+    /// sha256 of the state sent to the provider, hex encoded
+    pub state_hash: String,
+    /// sha256 of the binding cookie of the browser that started the login
+    pub browser_hash: String,
+    pub provider: String,
+    /// PKCE verifier sent with the code to the provider's token endpoint
+    pub code_verifier: String,
+    /// ends up in our id_token
+    pub nonce: Option<String>,
+    pub scope: String,
+    /// set when the login answers an authorization request of one of our clients, stored as json
+    pub pending_authorization: Option<PendingAuthorization>,
+    /// set when the login links the provider account to this user
+    pub link_user_id: Option<u32>,
+    pub expires_at: i64,
+    pub used_at: Option<i64>,
+}
@@ -1,2 +1,13 @@
 pub mod user_entity;
-pub mod srp;
\ No newline at end of file
+pub mod srp;
+pub mod jwk_entities;
+pub mod oidc_entities;
+pub mod token_entities;
+pub mod refresh_token_entity;
+pub mod oauth_client_entity;
+pub mod introspection_entities;
+pub mod authorization_code_entity;
+pub mod authorization_entities;
+pub mod device_authorization_entity;
+pub mod login_state_entity;
+pub mod external_identity_entity;
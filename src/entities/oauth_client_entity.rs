@@ -0,0 +1,22 @@
+/// a service or app registered to call the oauth endpoints
+#[derive(Debug)]
+pub struct OAuthClientEntity {
+    pub client_id: String,
+    /// sha256 of the client secret, hex encoded. public clients such as mobile apps have none
+    pub secret_hash: Option<String>,
+    pub name: String,
+    /// exact urls authorization codes may be sent to
+    pub redirect_uris: Vec<String>,
+    /// space delimited scopes the client may ask for in its own name
+    pub allowed_scopes: String,
+}
+
+impl OAuthClientEntity {
+    pub fn is_public(&self) -> bool {
+        self.secret_hash.is_none()
+    }
+
+    pub fn allows_redirect(&self, redirect_uri: &str) -> bool {
+        self.redirect_uris.iter().any(|registered| registered == redirect_uri)
+    }
+}
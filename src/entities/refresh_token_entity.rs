@@ -0,0 +1,45 @@
+use crate::services::jwt_service::SessionType;
+
+/// one refresh token, every rotation adds a row to the family of the login it started from
+#[derive(Debug)]
+pub struct RefreshTokenEntity {
+    pub id: u64,
+    pub family_id: String,
+    /// when the login of the family happened in milliseconds, a later subject revocation ends the whole family
+    pub family_issued_at: i64,
+    pub user_id: u32,
+    pub email: String,
+    pub session_type: SessionType,
+    /// granted at login, kept by every rotation
+    pub scope: String,
+    /// the oauth client the family was issued to, only it can rotate the tokens. None for our own logins
+    pub client_id: Option<String>,
+    pub expires_at: i64,
+    pub used_at: Option<i64>,
+    pub revoked_at: Option<i64>,
+}
+
+/// what every token of a family shares, fixed by the login it started from
+#[derive(Debug)]
+pub struct RefreshTokenFamily {
+    pub family_id: String,
+    /// in milliseconds
+    pub issued_at: i64,
+    pub user_id: u32,
+    pub session_type: SessionType,
+    pub scope: String,
+    pub client_id: Option<String>,
+}
+
+impl RefreshTokenEntity {
+    pub fn family(&self) -> RefreshTokenFamily {
+        RefreshTokenFamily {
+            family_id: self.family_id.clone(),
+            issued_at: self.family_issued_at,
+            user_id: self.user_id,
+            session_type: self.session_type,
+            scope: self.scope.clone(),
+            client_id: self.client_id.clone(),
+        }
+    }
+}
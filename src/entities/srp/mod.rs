@@ -1 +1,2 @@
-pub mod srp_entities;
\ No newline at end of file
+pub mod srp_entities;
+pub mod srp_group;
\ No newline at end of file
@@ -1,6 +1,8 @@
 use serde::{Deserialize, Serialize};
 use serde_json;
 
+use crate::entities::srp::srp_group::SrpGroup;
+
 #[derive(Deserialize, Serialize, Debug)]
 pub struct Link {
     pub rel: String,
@@ -15,17 +17,96 @@ pub struct SrpStep1Request {
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct SrpStep1Response {
+    pub handshake_id: String,
     pub public_b_str: String,
     pub salt_str: String,
+    /// the group the identity is registered with, a client that computed A
+    /// in another group must restart step 1 with this one
+    pub group: SrpGroupResponse,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct SrpGroupResponse {
+    pub name: SrpGroup,
+    pub n_str: String,
+    pub g_str: String,
+}
+
+impl SrpGroupResponse {
+    pub fn new(group: SrpGroup) -> Self {
+        SrpGroupResponse {
+            name: group,
+            n_str: group.n().to_string(),
+            g_str: group.g().to_string(),
+        }
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct SrpStep2Request {
+    pub handshake_id: String,
     pub identity: String,
     pub m1_str: String,
+    /// OIDC nonce, echoed into the id_token
+    #[serde(default)]
+    pub nonce: Option<String>,
+    /// space delimited scopes for the access token, all supported ones when missing
+    #[serde(default)]
+    pub scope: Option<String>,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct SrpStep2Response {
     pub m2_str: String,
+    pub access_token: String,
+    /// lifetime of the access token in seconds
+    pub expires_in: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    pub id_token: String,
+    pub scope: String,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct SrpRegisterRequest {
+    pub identity: String,
+    pub salt_str: String,
+    pub verifier_str: String,
+    pub group: SrpGroup,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub phone_number: String,
+    pub language_id: Option<i32>,
+}
+
+/// new srp credentials for the authenticated user, authorized by a fresh
+/// srp proof (handshake_id and m1_str from /srp/1)
+#[derive(Deserialize, Serialize, Debug)]
+pub struct PasswordChangeRequest {
+    pub handshake_id: String,
+    pub m1_str: String,
+    pub salt_str: String,
+    pub verifier_str: String,
+    pub group: SrpGroup,
+}
+
+/// new srp credentials for a user who forgot the password, authorized by a reset token issued for the identity
+#[derive(Deserialize, Serialize, Debug)]
+pub struct PasswordResetConfirmation {
+    pub identity: String,
+    pub reset_token: String,
+    pub salt_str: String,
+    pub verifier_str: String,
+    pub group: SrpGroup,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct PasswordResetRequest {
+    pub identity: String,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct PasswordResetResponse {
+    pub reset_token: String,
+    pub expires_at: i64,
 }
\ No newline at end of file
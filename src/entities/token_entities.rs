@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+
+/// tokens handed out at the end of a login or a refresh
+#[derive(Deserialize, Serialize, Debug)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub token_type: String,
+    /// lifetime of the access token in seconds
+    pub expires_in: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id_token: Option<String>,
+    /// space delimited scopes of the access token
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct RefreshTokenRequest {
+    pub refresh_token: String,
+    /// narrows the access token to some of the scopes granted at login
+    #[serde(default)]
+    pub scope: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct LogoutRequest {
+    /// refresh token of the session, its whole family is revoked with the access token
+    pub refresh_token: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct RevokeUserRequest {
+    pub identity: String,
+}
+
+/// form body of POST /oauth/token, RFC 6749 4.1.3, 4.4.2 and 6, RFC 8628 3.4
+#[derive(Deserialize, Serialize, Debug)]
+pub struct TokenRequest {
+    pub grant_type: String,
+    pub code: Option<String>,
+    pub redirect_uri: Option<String>,
+    /// public clients name themselves here, confidential ones use basic credentials
+    pub client_id: Option<String>,
+    pub code_verifier: Option<String>,
+    /// client_credentials only, narrows the scopes allowed to the client
+    pub scope: Option<String>,
+    pub device_code: Option<String>,
+    pub refresh_token: Option<String>,
+}
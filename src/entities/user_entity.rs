@@ -1,4 +1,5 @@
 use crate::ouath::oauth::ExternalAccount;
+use crate::entities::srp::srp_group::SrpGroup;
 use serde::{Serialize, Deserialize};
 use actix_web::{Responder, HttpRequest, Error, HttpResponse};
 use actix_web::body::Body;
@@ -13,7 +14,8 @@ pub struct UserEntity {
     pub phone_number: String,
     pub language_id: i32,
     pub salt: Option<String>,
-    pub verifier: Option<String>
+    pub verifier: Option<String>,
+    pub srp_group: Option<SrpGroup>
 }
 
 impl UserEntity {
@@ -26,7 +28,8 @@ impl UserEntity {
             id: None,
             language_id: 1,
             salt: None,
-            verifier: None
+            verifier: None,
+            srp_group: None
         }
     }
 }
@@ -21,7 +21,10 @@ pub enum ErrorCode {
 #[derive(Debug)]
 pub enum HttpErrorCode {
     BadRequest {message: ErrorResponse},
-    UnAuthorized {message: ErrorResponse}
+    UnAuthorized {message: ErrorResponse},
+    Forbidden {message: ErrorResponse},
+    Conflict {message: ErrorResponse},
+    ServiceUnavailable {message: ErrorResponse}
 }
 
 impl Display for HttpErrorCode {
@@ -33,6 +36,15 @@ impl Display for HttpErrorCode {
             HttpErrorCode::UnAuthorized { message: error_detail } => {
                 write!(f, "({}, {})", error_detail.message, error_detail.error_code)
             }
+            HttpErrorCode::Forbidden { message: error_detail } => {
+                write!(f, "({}, {})", error_detail.message, error_detail.error_code)
+            }
+            HttpErrorCode::Conflict { message: error_detail } => {
+                write!(f, "({}, {})", error_detail.message, error_detail.error_code)
+            }
+            HttpErrorCode::ServiceUnavailable { message: error_detail } => {
+                write!(f, "({}, {})", error_detail.message, error_detail.error_code)
+            }
         }
     }
 }
@@ -47,6 +59,15 @@ impl error::ResponseError for HttpErrorCode {
             HttpErrorCode::UnAuthorized { .. } => {
                 StatusCode::UNAUTHORIZED
             }
+            HttpErrorCode::Forbidden { .. } => {
+                StatusCode::FORBIDDEN
+            }
+            HttpErrorCode::Conflict { .. } => {
+                StatusCode::CONFLICT
+            }
+            HttpErrorCode::ServiceUnavailable { .. } => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
         }
     }
 
@@ -1,25 +1,32 @@
 use std::borrow::Borrow;
-use std::convert::TryFrom;
+use std::cell::RefCell;
 use std::pin::Pin;
 use std::process;
-use std::sync::{Mutex, MutexGuard, PoisonError, RwLock};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock};
 use std::task::{Context, Poll};
 use std::thread::Thread;
 
 use actix_service::{Service, Transform};
-use actix_web::{dev::RequestHead, FromRequest, guard::Guard, http, HttpRequest, HttpResponse, web};
+use actix_web::{dev::RequestHead, FromRequest, guard::Guard, http, HttpMessage, HttpRequest, HttpResponse, web};
 use actix_web::{dev::ServiceRequest, dev::ServiceResponse, Error};
 use actix_web::dev::{Payload, PayloadStream};
 use actix_web::error::{ErrorUnauthorized, PayloadError};
-use actix_web::http::{HeaderName, HeaderValue};
+use actix_web::http::HeaderMap;
 use actix_web::web::{Bytes, Data};
 use futures::{Future, FutureExt, Stream, TryFutureExt, TryStreamExt};
 use futures::future::{Either, err, ok, Ready};
 use log::debug;
 
-use crate::services::jwt_service::{JwtClaims, SessionType, verify};
+use crate::filters::route_policy::{RouteAccess, RoutePolicy};
+use crate::services::jwt_service::{JwtClaims, verify};
+use crate::services::revocation_store::RevocationStore;
+use crate::services::key_manager::KeyManager;
 use crate::UserPrinciple;
 
+/// request headers that once carried the principal
+const SPOOFABLE_HEADERS: [&str; 3] = ["is_valid", "email", "session_type"];
+
 pub struct ContentTypeHeader;
 
 pub struct MethodAllowed;
@@ -46,15 +53,26 @@ impl Guard for MethodAllowed {
     }
 }
 
-pub struct AuthFilter;
+pub struct AuthFilter {
+    policy: Arc<RoutePolicy>
+}
+
+impl AuthFilter {
+    pub fn new(policy: Arc<RoutePolicy>) -> Self {
+        AuthFilter {
+            policy
+        }
+    }
+}
 
 pub struct AuthFilterMiddleware<S> {
-    service: S
+    service: Rc<RefCell<S>>,
+    policy: Arc<RoutePolicy>
 }
 
 impl<S, B> Transform<S> for AuthFilter
     where
-        S: Service<Request=ServiceRequest, Response=ServiceResponse<B>, Error=Error>,
+        S: Service<Request=ServiceRequest, Response=ServiceResponse<B>, Error=Error> + 'static,
         S::Future: 'static,
         B: 'static,
 {
@@ -66,13 +84,13 @@ impl<S, B> Transform<S> for AuthFilter
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ok(AuthFilterMiddleware { service })
+        ok(AuthFilterMiddleware { service: Rc::new(RefCell::new(service)), policy: self.policy.clone() })
     }
 }
 
 impl<S, B> Service for AuthFilterMiddleware<S>
     where
-        S: Service<Request=ServiceRequest, Response=ServiceResponse<B>, Error=Error>,
+        S: Service<Request=ServiceRequest, Response=ServiceResponse<B>, Error=Error> + 'static,
         S::Future: 'static,
         B: 'static,
 {
@@ -82,36 +100,35 @@ impl<S, B> Service for AuthFilterMiddleware<S>
     type Future = Pin<Box<dyn Future<Output=Result<Self::Response, Self::Error>>>>;
 
     fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        self.service.poll_ready(cx)
+        self.service.borrow_mut().poll_ready(cx)
     }
 
     fn call(&mut self, mut req: ServiceRequest) -> Self::Future {
+        // the principal only travels in request extensions, headers of these names
+        // used to carry it and are dropped so no handler or backend can be fooled by them
+        for name in SPOOFABLE_HEADERS.iter() {
+            req.headers_mut().remove(*name);
+        }
         if req.method().as_str() == "OPTIONS" {
             Box::pin(async move {
                 let res = req.into_response(HttpResponse::Ok().finish().into_body());
                 Ok(res)
             })
         } else {
-            let path = req.path();
-            if path.contains("iot/auth2/") || path.contains("srp"){
-                let fut = self.service.call(req);
+            let access = self.policy.access(req.method(), req.path());
+            let anonymous = access == RouteAccess::OPTIONAL && !req.headers().contains_key("Authorization");
+            if access == RouteAccess::PUBLIC || anonymous {
+                let fut = self.service.borrow_mut().call(req);
                 Box::pin(async move {
                     let res = fut.await?;
                     Ok(res)
                 })
             } else {
                 if req.headers().contains_key("Authorization") {
-                    let authorization = req.headers().get("Authorization").unwrap();
-                    let auth_value = authorization.to_str().unwrap();
-                    let mut jwt = "";
-                    for (index, v) in auth_value.char_indices() {
-                        if index == 7 {
-                            jwt = &auth_value[7..];
-                            break;
-                        }
-                    }
-
-                    match verify(&jwt.to_string()) {
+                    // no key manager registered means nothing can be verified
+                    let claims = req.app_data::<web::Data<KeyManager>>()
+                        .and_then(|keys| bearer_claims(req.headers(), keys.get_ref()));
+                    match claims {
                         None => {
                             Box::pin(async move {
                                 let res = req.into_response(HttpResponse::Unauthorized().finish().into_body());
@@ -120,15 +137,17 @@ impl<S, B> Service for AuthFilterMiddleware<S>
                         }
                         Some(claim) => {
                             // found claim
-                            let email = &claim.sub.unwrap();
-
-                            let h = req.headers_mut();
-                            h.insert(HeaderName::from_static("is_valid"), HeaderValue::try_from("true".to_string()).unwrap());
-                            h.insert(HeaderName::from_static("email"), HeaderValue::try_from(email).unwrap());
-                            h.insert(HeaderName::from_static("session_type"), HeaderValue::try_from(claim.session_type.unwrap().clone().to_string()).unwrap());
-                            let fut = self.service.call(req);
+                            let revocation_store = req.app_data::<web::Data<dyn RevocationStore>>().cloned();
+                            req.extensions_mut().insert(UserPrinciple::from(&claim));
+                            let service = self.service.clone();
 
                             Box::pin(async move {
+                                if let Some(store) = revocation_store {
+                                    if store.is_revoked(&claim).await {
+                                        return Ok(req.into_response(HttpResponse::Unauthorized().finish().into_body()));
+                                    }
+                                }
+                                let fut = service.borrow_mut().call(req);
                                 let res = fut.await?;
                                 Ok(res)
                             })
@@ -147,21 +166,51 @@ impl<S, B> Service for AuthFilterMiddleware<S>
     }
 }
 
+/// the token of a "bearer <token>" Authorization header, the scheme is case insensitive
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    let auth_value = headers.get("Authorization")?.to_str().ok()?;
+    if !auth_value.get(..7)?.eq_ignore_ascii_case("bearer ") {
+        return None;
+    }
+    Some(auth_value[7..].trim())
+}
+
+/// claims of a token that opens a session.
+/// id_tokens and oauth state carry no session type and never open a session
+pub fn session_claims(token: &str, keys: &KeyManager) -> Option<JwtClaims> {
+    verify(keys, &token.to_string())
+        .filter(|claim| claim.sub.is_some() && claim.session_type.is_some())
+}
+
+/// claims of the bearer token in the Authorization header
+pub fn bearer_claims(headers: &HeaderMap, keys: &KeyManager) -> Option<JwtClaims> {
+    session_claims(bearer_token(headers)?, keys)
+}
+
+impl From<&JwtClaims> for UserPrinciple {
+    fn from(claims: &JwtClaims) -> Self {
+        UserPrinciple {
+            email: claims.sub.clone(),
+            session_type: claims.session_type,
+            jwt_id: claims.jwt_id.clone(),
+            aud: claims.aud.clone(),
+            scopes: claims.scopes(),
+            permissions: claims.permissions.clone(),
+            expires_at: claims.exp,
+        }
+    }
+}
+
+/// the principal the auth filter stored for a verified bearer token
 impl FromRequest for UserPrinciple {
     type Error = Error;
     type Future = Ready<Result<Self, Self::Error>>;
     type Config = ();
 
-    fn from_request(req: &HttpRequest, payload: &mut Payload<PayloadStream>) -> Self::Future {
-        if req.headers().contains_key("is_valid") {
-            let email = req.headers().get("email").unwrap().to_str().unwrap();
-            let session_type = req.headers().get("session_type").unwrap().to_str().unwrap().parse::<SessionType>().unwrap();
-            ok(UserPrinciple {
-                email: Some(email.to_string()),
-                session_type: Some(session_type),
-            })
-        } else {
-            err(ErrorUnauthorized("no valid session found"))
+    fn from_request(req: &HttpRequest, _payload: &mut Payload<PayloadStream>) -> Self::Future {
+        match req.extensions().get::<UserPrinciple>() {
+            Some(principal) => ok(principal.clone()),
+            None => err(ErrorUnauthorized("no valid session found"))
         }
     }
 }
@@ -204,16 +253,31 @@ mod test {
     use std::time::SystemTime;
 
     use actix_web::{App, test, web};
-    use actix_web::http::StatusCode;
+    use actix_web::http::{HeaderName, HeaderValue, StatusCode};
     use chrono::{Duration, NaiveDateTime, Timelike, Utc};
     use env_logger::Env;
     use futures::task::SpawnExt;
 
-    use crate::services::jwt_service::{issue, SessionType};
+    use crate::services::key_manager::{test_claims, test_token};
     use crate::restful::echo_resource;
 
     use super::*;
     use crate::filters::{authentication_filter, cors_filter};
+    use crate::services::revocation_store::InMemoryRevocationStore;
+    use crate::restful::srp_resource;
+
+    #[test]
+    fn test_bearer_scheme() {
+        let headers = |authorization: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(HeaderName::from_static("authorization"), HeaderValue::from_str(authorization).unwrap());
+            headers
+        };
+        assert_eq!(bearer_token(&headers("Bearer abc")), Some("abc"));
+        assert_eq!(bearer_token(&headers("BEARER abc")), Some("abc"));
+        assert_eq!(bearer_token(&headers("Digest abc")), None);
+        assert_eq!(bearer_token(&headers("bearer")), None);
+    }
 
     #[actix_rt::test]
     async fn test_authorization_header_not_exist() {
@@ -222,8 +286,9 @@ mod test {
         env_logger::try_init();
 
         let c = web::Data::new(echo_resource::AppStateWithCounter::new());
-        let mut app = test::init_service(App::new().wrap(authentication_filter::AuthFilter)
+        let mut app = test::init_service(App::new().wrap(auth_filter())
             .app_data(c.clone())
+            .app_data(keys())
             .configure(echo_resource::config)).await;
         let req = test::TestRequest::with_header("content-type", "application/json").uri("/echo/counter").to_request();
         let resp = test::call_service(&mut app, req).await;
@@ -261,9 +326,10 @@ mod test {
 
         let c = web::Data::new(echo_resource::AppStateWithCounter::new());
         let mut app = test::init_service(App::new()
-            .wrap(authentication_filter::AuthFilter)
+            .wrap(auth_filter())
             .wrap(cors_filter::CorsFilter)
             .app_data(c.clone())
+            .app_data(keys())
             .configure(echo_resource::config)).await;
 
         let mut req_builder = test::TestRequest::with_header("content-type", "application/json").uri("/echo/counter");
@@ -281,18 +347,157 @@ mod test {
         assert_eq!(StatusCode::OK, resp.status());
     }
 
+    #[actix_rt::test]
+    async fn test_revoked_subject_rejected() {
+        let c = web::Data::new(echo_resource::AppStateWithCounter::new());
+        let store: Arc<dyn RevocationStore> = Arc::new(InMemoryRevocationStore::new());
+        let revocation_store = web::Data::from(store);
+        let mut app = test::init_service(App::new()
+            .wrap(auth_filter())
+            .app_data(c.clone())
+            .app_data(keys())
+            .app_data(revocation_store.clone())
+            .configure(echo_resource::config)).await;
+
+        let mut req_builder = test::TestRequest::with_header("content-type", "application/json").uri("/echo/counter");
+        let valid_token = generate_token_issued_at("moe@gmail.com", Utc::now().timestamp() as usize - 60);
+        req_builder = req_builder.header("Authorization", format!("bearer {}", valid_token));
+        let resp = test::call_service(&mut app, req_builder.to_request()).await;
+        assert_eq!(StatusCode::OK, resp.status());
+
+        // a password change ends every session issued before it, without iat_ms also the ones of its second
+        revocation_store.revoke_subject("moe@gmail.com").await;
+        for token in [valid_token, generate_valid_token("moe@gmail.com")] {
+            let req_builder = test::TestRequest::with_header("content-type", "application/json").uri("/echo/counter")
+                .header("Authorization", format!("bearer {}", token));
+            let resp = test::call_service(&mut app, req_builder.to_request()).await;
+            assert_eq!(StatusCode::UNAUTHORIZED, resp.status());
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_revoked_token_rejected() {
+        let c = web::Data::new(echo_resource::AppStateWithCounter::new());
+        let store: Arc<dyn RevocationStore> = Arc::new(InMemoryRevocationStore::new());
+        let revocation_store = web::Data::from(store);
+        let mut app = test::init_service(App::new()
+            .wrap(auth_filter())
+            .app_data(c.clone())
+            .app_data(keys())
+            .app_data(revocation_store.clone())
+            .configure(echo_resource::config)).await;
+
+        // logout revokes the presented token only
+        let logged_out = generate_valid_token("moe@gmail.com");
+        let other_session = generate_valid_token("moe@gmail.com");
+        let claims = verify(keys().get_ref(), &logged_out).unwrap();
+        revocation_store.revoke_token(claims.jwt_id.as_ref().unwrap(), claims.exp).await;
+        for (token, status) in [(logged_out, StatusCode::UNAUTHORIZED), (other_session, StatusCode::OK)] {
+            let req_builder = test::TestRequest::with_header("content-type", "application/json").uri("/echo/counter")
+                .header("Authorization", format!("bearer {}", token));
+            let resp = test::call_service(&mut app, req_builder.to_request()).await;
+            assert_eq!(status, resp.status());
+        }
+    }
+
+    async fn whoami(user: Option<UserPrinciple>, req: HttpRequest) -> HttpResponse {
+        let leaked = SPOOFABLE_HEADERS.iter().any(|name| req.headers().contains_key(*name));
+        HttpResponse::Ok().body(format!("{:?} {}", user.and_then(|user| user.email), leaked))
+    }
+
+    #[actix_rt::test]
+    async fn test_spoofed_principal_headers_ignored() {
+        let mut app = test::init_service(App::new()
+            .wrap(auth_filter())
+            .app_data(keys())
+            .route("/srp/whoami", web::get().to(whoami))
+            .route("/echo/whoami", web::get().to(whoami))).await;
+
+        // a whitelisted path never gets a principal out of client headers
+        let req = test::TestRequest::get().uri("/srp/whoami")
+            .header("is_valid", "true")
+            .header("email", "admin@gmail.com")
+            .header("session_type", "SYSADMIN")
+            .to_request();
+        assert_eq!(test::read_response(&mut app, req).await, "None false");
+
+        // on a protected path the principal comes from the token, not from the headers
+        let req = test::TestRequest::get().uri("/echo/whoami")
+            .header("Authorization", format!("bearer {}", generate_valid_token("moe@gmail.com")))
+            .header("is_valid", "true")
+            .header("email", "admin@gmail.com")
+            .header("session_type", "SYSADMIN")
+            .to_request();
+        assert_eq!(test::read_response(&mut app, req).await, "Some(\"moe@gmail.com\") false");
+
+        let req = test::TestRequest::get().uri("/echo/whoami")
+            .header("is_valid", "true")
+            .header("email", "admin@gmail.com")
+            .to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_rt::test]
+    async fn test_optional_authentication() {
+        let mut app = test::init_service(App::new()
+            .wrap(auth_filter())
+            .app_data(keys())
+            .route("/optional/whoami", web::get().to(whoami))).await;
+
+        let req = test::TestRequest::get().uri("/optional/whoami").to_request();
+        assert_eq!(test::read_response(&mut app, req).await, "None false");
+
+        let req = test::TestRequest::get().uri("/optional/whoami")
+            .header("Authorization", format!("bearer {}", generate_valid_token("moe@gmail.com")))
+            .to_request();
+        assert_eq!(test::read_response(&mut app, req).await, "Some(\"moe@gmail.com\") false");
+
+        // a token that is sent must be valid
+        let req = test::TestRequest::get().uri("/optional/whoami")
+            .header("Authorization", "bearer crap")
+            .to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_rt::test]
+    async fn test_id_token_is_not_a_session() {
+        let c = web::Data::new(echo_resource::AppStateWithCounter::new());
+        let mut app = test::init_service(App::new()
+            .wrap(auth_filter())
+            .app_data(c.clone())
+            .app_data(keys())
+            .configure(echo_resource::config)).await;
+
+        let id_token = crate::services::oidc_service::test_oidc_configuration()
+            .id_token(keys().get_ref(), "infotamia", "moe@gmail.com", None, Utc::now().timestamp() as usize);
+        let req_builder = test::TestRequest::with_header("content-type", "application/json").uri("/echo/counter")
+            .header("Authorization", format!("bearer {}", id_token));
+        let resp = test::call_service(&mut app, req_builder.to_request()).await;
+        assert_eq!(StatusCode::UNAUTHORIZED, resp.status());
+    }
+
+    fn auth_filter() -> AuthFilter {
+        let mut policy = RoutePolicy::new();
+        srp_resource::policy(&mut policy);
+        policy.prefix("/optional/", RouteAccess::OPTIONAL);
+        AuthFilter::new(Arc::new(policy))
+    }
+
+    fn keys() -> web::Data<KeyManager> {
+        web::Data::new(crate::services::key_manager::test_key_manager())
+    }
+
     fn generate_valid_token(email: &str) -> String {
-        let mut claims = JwtClaims {
-            aud: Some("".to_string()),
-            exp: Utc::now().add(Duration::days(1)).timestamp() as usize,
-            iat: Utc::now().timestamp() as usize,
-            issuer: Some("infotamia".to_string()),
-            jwt_id: Some("myid".to_string()),
+        generate_token_issued_at(email, Utc::now().timestamp() as usize)
+    }
+
+    fn generate_token_issued_at(email: &str, iat: usize) -> String {
+        test_token(JwtClaims {
             sub: Some(email.to_string()),
             access_token: Some("sometoken".to_string()),
-            session_type: Some(SessionType::USER),
-        };
-
-        issue(&mut claims)
+            iat,
+            exp: Utc::now().add(Duration::days(1)).timestamp() as usize,
+            ..test_claims()
+        })
     }
 }
\ No newline at end of file
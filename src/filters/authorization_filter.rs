@@ -0,0 +1,146 @@
+use std::pin::Pin;
+use std::rc::Rc;
+
+use actix_service::{Service, Transform};
+use actix_web::{Error, HttpMessage};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use futures::future::{ok, Ready};
+use futures::Future;
+use futures::task::{Context, Poll};
+
+use crate::exceptions::error_base::{ErrorResponse, HttpErrorCode};
+use crate::services::jwt_service::Permission;
+use crate::UserPrinciple;
+
+/// resource middleware answering 403 unless the principal holds the permission,
+/// registered with `web::resource(..).wrap(RequirePermission(Permission::GLOBAL_DELETE))`
+pub struct RequirePermission(pub Permission);
+
+/// resource or scope middleware answering 403 unless the token was granted the OAuth2 scope
+pub struct RequireScope(pub &'static str);
+
+pub struct AuthorizationMiddleware<S> {
+    service: S,
+    allows: Rc<dyn Fn(&UserPrinciple) -> bool>,
+    requirement: String,
+}
+
+impl<S, B> Transform<S> for RequirePermission
+    where
+        S: Service<Request=ServiceRequest, Response=ServiceResponse<B>, Error=Error>,
+        S::Future: 'static,
+        B: 'static, {
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = AuthorizationMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        let permission = self.0;
+        ok(AuthorizationMiddleware {
+            service,
+            allows: Rc::new(move |user: &UserPrinciple| user.permissions.contains(&permission)),
+            requirement: format!("{} permission required", permission),
+        })
+    }
+}
+
+impl<S, B> Transform<S> for RequireScope
+    where
+        S: Service<Request=ServiceRequest, Response=ServiceResponse<B>, Error=Error>,
+        S::Future: 'static,
+        B: 'static, {
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = AuthorizationMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        let scope = self.0;
+        ok(AuthorizationMiddleware {
+            service,
+            allows: Rc::new(move |user: &UserPrinciple| user.scopes.iter().any(|granted| granted == scope)),
+            requirement: format!("{} scope required", scope),
+        })
+    }
+}
+
+impl<S, B> Service for AuthorizationMiddleware<S>
+    where
+        S: Service<Request=ServiceRequest, Response=ServiceResponse<B>, Error=Error>,
+        S::Future: 'static,
+        B: 'static, {
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output=Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, ctx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(ctx)
+    }
+
+    fn call(&mut self, req: Self::Request) -> Self::Future {
+        // the principal was put there by the auth filter, a request without one is never allowed
+        let allowed = req.extensions().get::<UserPrinciple>().is_some_and(|user| (self.allows)(user));
+        if allowed {
+            Box::pin(self.service.call(req))
+        } else {
+            let forbidden = HttpErrorCode::Forbidden {message : ErrorResponse {message: self.requirement.clone(), error_code : "forbidden".to_string()}};
+            Box::pin(ok(req.error_response(forbidden)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use actix_web::{App, HttpResponse, test, web};
+    use actix_web::http::StatusCode;
+
+    use super::*;
+    use crate::filters::authentication_filter::AuthFilter;
+    use crate::filters::route_policy::RoutePolicy;
+    use crate::services::jwt_service::JwtClaims;
+    use crate::services::key_manager::{test_claims, test_key_manager, test_token};
+
+    fn token(permissions: Vec<Permission>, scope: Option<&str>) -> String {
+        test_token(JwtClaims {
+            permissions,
+            scope: scope.map(|scope| scope.to_string()),
+            ..test_claims()
+        })
+    }
+
+    #[actix_rt::test]
+    async fn test_requirements_answer_forbidden() {
+        let mut app = test::init_service(App::new()
+            .wrap(AuthFilter::new(Arc::new(RoutePolicy::new())))
+            .app_data(web::Data::new(test_key_manager()))
+            .service(web::resource("/delete")
+                .wrap(RequirePermission(Permission::GLOBAL_DELETE))
+                .route(web::post().to(HttpResponse::NoContent)))
+            .service(web::scope("/devices")
+                .wrap(RequireScope("devices:write"))
+                .route("/1", web::post().to(HttpResponse::NoContent)))).await;
+
+        let cases = vec![
+            ("/delete", token(vec![Permission::GLOBAL_DELETE], None), StatusCode::NO_CONTENT),
+            ("/delete", token(vec![Permission::GLOBAL_READ], None), StatusCode::FORBIDDEN),
+            ("/devices/1", token(vec![], Some("openid devices:write")), StatusCode::NO_CONTENT),
+            ("/devices/1", token(vec![], Some("devices:read")), StatusCode::FORBIDDEN),
+            ("/devices/1", token(vec![], None), StatusCode::FORBIDDEN),
+        ];
+        for (path, token, status) in cases {
+            let req = test::TestRequest::post().uri(path)
+                .header("Authorization", format!("bearer {}", token))
+                .to_request();
+            let res = test::call_service(&mut app, req).await;
+            assert_eq!(res.status(), status, "{}", path);
+        }
+    }
+}
@@ -1,2 +1,4 @@
 pub mod authentication_filter;
+pub mod authorization_filter;
 pub mod cors_filter;
+pub mod route_policy;
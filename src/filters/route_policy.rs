@@ -0,0 +1,118 @@
+use actix_web::http::Method;
+
+/// what the auth filter demands of a request
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RouteAccess {
+    /// no token is looked at, handlers never see a principal
+    PUBLIC,
+    /// a token is verified when sent, requests without one pass anonymously
+    OPTIONAL,
+    /// a valid, unrevoked bearer token is required
+    AUTHENTICATED,
+}
+
+#[derive(Debug)]
+enum PathMatch {
+    Exact(String),
+    Prefix(String),
+}
+
+#[derive(Debug)]
+struct RouteRule {
+    method: Option<Method>,
+    path: PathMatch,
+    access: RouteAccess,
+}
+
+impl RouteRule {
+    /// None when the rule does not apply, otherwise how specific the match is
+    fn specificity(&self, method: &Method, path: &str) -> Option<(bool, usize, bool)> {
+        if self.method.as_ref().is_some_and(|m| m != method) {
+            return None;
+        }
+        match self.path {
+            PathMatch::Exact(ref exact) if exact == path => Some((true, exact.len(), self.method.is_some())),
+            PathMatch::Prefix(ref prefix) if path.starts_with(prefix.as_str()) => Some((false, prefix.len(), self.method.is_some())),
+            _ => None
+        }
+    }
+}
+
+/// access rules of every route, built at startup from the policy functions next to each resource config.
+/// the most specific rule wins: exact paths over prefixes, longer prefixes over shorter ones and
+/// method rules over rules for any method. routes without a rule need authentication
+#[derive(Debug)]
+pub struct RoutePolicy {
+    rules: Vec<RouteRule>,
+    default_access: RouteAccess,
+}
+
+impl RoutePolicy {
+    pub fn new() -> Self {
+        RoutePolicy {
+            rules: vec![],
+            default_access: RouteAccess::AUTHENTICATED,
+        }
+    }
+
+    pub fn exact(&mut self, path: &str, access: RouteAccess) -> &mut Self {
+        self.rule(None, PathMatch::Exact(normalize(path)), access)
+    }
+
+    /// every path under the prefix, a scope registered by a resource config
+    pub fn prefix(&mut self, prefix: &str, access: RouteAccess) -> &mut Self {
+        self.rule(None, PathMatch::Prefix(normalize(prefix)), access)
+    }
+
+    /// one path for one method only
+    pub fn method(&mut self, method: Method, path: &str, access: RouteAccess) -> &mut Self {
+        self.rule(Some(method), PathMatch::Exact(normalize(path)), access)
+    }
+
+    pub fn access(&self, method: &Method, path: &str) -> RouteAccess {
+        let path = normalize(path);
+        self.rules.iter()
+            .filter_map(|rule| rule.specificity(method, &path).map(|specificity| (specificity, rule.access)))
+            .max_by_key(|(specificity, _)| *specificity)
+            .map_or(self.default_access, |(_, access)| access)
+    }
+
+    fn rule(&mut self, method: Option<Method>, path: PathMatch, access: RouteAccess) -> &mut Self {
+        self.rules.push(RouteRule { method, path, access });
+        self
+    }
+}
+
+/// scopes like "/srp/" joined with "/1" register "/srp//1", collapse repeated slashes on both sides
+fn normalize(path: &str) -> String {
+    let mut normalized = String::with_capacity(path.len());
+    for c in path.chars() {
+        if c == '/' && normalized.ends_with('/') {
+            continue;
+        }
+        normalized.push(c);
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_most_specific_rule_wins() {
+        let mut policy = RoutePolicy::new();
+        policy.prefix("/srp/", RouteAccess::PUBLIC)
+            .prefix("/echo/", RouteAccess::OPTIONAL)
+            .exact("/echo/", RouteAccess::PUBLIC)
+            .method(Method::POST, "/echo/counter", RouteAccess::AUTHENTICATED);
+
+        assert_eq!(policy.access(&Method::POST, "/srp//1"), RouteAccess::PUBLIC);
+        assert_eq!(policy.access(&Method::GET, "/echo/"), RouteAccess::PUBLIC);
+        assert_eq!(policy.access(&Method::GET, "/echo/counter"), RouteAccess::OPTIONAL);
+        assert_eq!(policy.access(&Method::POST, "/echo/counter"), RouteAccess::AUTHENTICATED);
+        // substrings no longer open routes
+        assert_eq!(policy.access(&Method::GET, "/user/srp-anything"), RouteAccess::AUTHENTICATED);
+        assert_eq!(policy.access(&Method::GET, "/user/profile"), RouteAccess::AUTHENTICATED);
+    }
+}
@@ -15,12 +15,18 @@ use serde::export::Formatter;
 use db::connection_pool_manager::PoolInstantiate;
 use filters::{authentication_filter, cors_filter};
 use filters::authentication_filter::{ContentTypeHeader, MethodAllowed};
-use ouath::oauth::FacebookAuthenticationService;
-use restful::{echo_resource, facebook_resource, user_resource};
-use services::jwt_service::SessionType;
+use filters::route_policy::RoutePolicy;
+use ouath::provider::OAuthProviders;
+use restful::{echo_resource, social_login_resource, user_resource};
+use services::jwt_service::{Permission, SessionType};
 use std::iter::Map;
-use rust_srp::SrpServer;
-use crate::restful::srp_resource;
+use crate::restful::{auth_resource, oauth_resource, srp_resource, token_resource, well_known_resource};
+use crate::services::srp_service::SrpConfiguration;
+use crate::services::srp_session_store;
+use crate::services::revocation_store::{RevocationStore, MySqlRevocationStore};
+use crate::services::cleanup_service::CleanupService;
+use crate::services::key_manager::KeyManager;
+use crate::services::oidc_service::OidcConfiguration;
 
 mod daos;
 mod entities;
@@ -31,40 +37,114 @@ mod services;
 mod exceptions;
 mod ouath;
 
+/// identity of a verified bearer token, put into the request extensions by the auth filter
 #[derive(Debug, Clone)]
 pub struct UserPrinciple {
     email: Option<String>,
     session_type: Option<SessionType>,
+    jwt_id: Option<String>,
+    aud: Option<String>,
+    scopes: Vec<String>,
+    permissions: Vec<Permission>,
+    expires_at: usize,
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    let srp_session_management:HashMap<String, SrpServer> = HashMap::new();
-    let srp_session_management = web::Data::new(Mutex::new(srp_session_management));
     let counter = web::Data::new(echo_resource::AppStateWithCounter::new());
     std::env::set_var("RUST_LOG", "debug");
     std::env::set_var("RUST_BACKTRACE", "1");
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
     let pool = PoolInstantiate::init().await;
+    let keys = Arc::new(KeyManager::new());
+    let srp_config = SrpConfiguration::new();
+    let oidc_config = web::Data::new(OidcConfiguration::new());
+    let srp_session_store = srp_session_store::build_session_store(&srp_config, &pool);
+    let eviction_store = srp_session_store.clone();
+    spawn_periodic(std::time::Duration::from_secs(srp_config.eviction_interval_seconds), move || {
+        let eviction_store = eviction_store.clone();
+        async move {
+            let evicted = eviction_store.evict_expired().await;
+            if evicted > 0 {
+                debug!("evicted {} expired srp handshakes", evicted);
+            }
+        }
+    });
+    let srp_session_store = web::Data::from(srp_session_store);
+    let srp_config = web::Data::new(srp_config);
+    let revocation_store: Arc<dyn RevocationStore> = Arc::new(MySqlRevocationStore::new(pool.clone(), oidc_config.max_token_lifetime_seconds()));
+    let revocation_eviction_store = revocation_store.clone();
+    spawn_periodic(std::time::Duration::from_secs(oidc_config.revocation_sync_interval_seconds), move || {
+        let revocation_eviction_store = revocation_eviction_store.clone();
+        async move {
+            let evicted = revocation_eviction_store.evict_expired().await;
+            if evicted > 0 {
+                debug!("evicted {} expired revocations", evicted);
+            }
+        }
+    });
+    let revocation_store = web::Data::from(revocation_store);
+    let cleanup_pool = pool.clone();
+    spawn_periodic(std::time::Duration::from_secs(oidc_config.cleanup_interval_seconds), move || {
+        let cleanup_pool = cleanup_pool.clone();
+        async move {
+            let deleted = CleanupService::new(&cleanup_pool).delete_expired().await;
+            if deleted > 0 {
+                debug!("deleted {} expired grants and tokens", deleted);
+            }
+        }
+    });
+    let oauth_providers = web::Data::new(OAuthProviders::new());
+    let keys = web::Data::from(keys);
+    let mut route_policy = RoutePolicy::new();
+    echo_resource::policy(&mut route_policy);
+    social_login_resource::policy(&mut route_policy);
+    user_resource::policy(&mut route_policy);
+    srp_resource::policy(&mut route_policy);
+    well_known_resource::policy(&mut route_policy);
+    token_resource::policy(&mut route_policy);
+    oauth_resource::policy(&mut route_policy);
+    let route_policy = Arc::new(route_policy);
     HttpServer::new(move || {
         App::new()
             .wrap(Logger::default())
-            .wrap(authentication_filter::AuthFilter)
+            .wrap(authentication_filter::AuthFilter::new(route_policy.clone()))
             .wrap(cors_filter::CorsFilter)
             .data_factory(|| -> Ready<Result<String, Error>>{
                 let x: u8 = random();
                 ok(format!("Thread-{}", x))
             })
-            .app_data(srp_session_management.clone())
+            .app_data(srp_session_store.clone())
+            .app_data(srp_config.clone())
+            .app_data(revocation_store.clone())
             .app_data(counter.clone())
             .data(pool.clone())
-            .data(FacebookAuthenticationService::new())
+            .app_data(oauth_providers.clone())
+            .app_data(keys.clone())
+            .app_data(oidc_config.clone())
             .configure(echo_resource::config)
-            .configure(facebook_resource::config)
+            .configure(social_login_resource::config)
             .configure(user_resource::config)
             .configure(srp_resource::config)
+            .configure(well_known_resource::config)
+            .configure(token_resource::config)
+            .configure(auth_resource::config)
+            .configure(oauth_resource::config)
     })
         .bind("0.0.0.0:8080")?
         .run()
         .await
 }
+
+/// run task every interval on the actix runtime, the first run is right away
+fn spawn_periodic<F, Fut>(interval: std::time::Duration, task: F)
+    where F: Fn() -> Fut + 'static,
+          Fut: std::future::Future<Output = ()> {
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(interval);
+        loop {
+            interval.tick().await;
+            task().await;
+        }
+    });
+}
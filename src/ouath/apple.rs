@@ -0,0 +1,235 @@
+use std::{fs, process};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use jsonwebtoken::{Algorithm, encode, EncodingKey, Header};
+use log::{error, info};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+use crate::ouath::jwks::{HttpJwksSource, JwksCache};
+use crate::ouath::oauth::{BaseOAuth20Service, ExternalAccount};
+use crate::ouath::provider::{ClientAuthentication, load_configuration, TokenEndpoint};
+
+const APPLE_ISSUER: &str = "https://appleid.apple.com";
+const APPLE_AUTHORIZATION_ENDPOINT: &str = "https://appleid.apple.com/auth/authorize";
+const APPLE_TOKEN_ENDPOINT: &str = "https://appleid.apple.com/auth/token";
+const APPLE_JWKS_URI: &str = "https://appleid.apple.com/auth/keys";
+
+/// apple accepts client secrets valid for up to six months, ours are made per token request
+const CLIENT_SECRET_TTL_SECONDS: i64 = 300;
+
+#[derive(Deserialize, Debug)]
+pub struct AppleConfiguration {
+    /// the services id sign in with apple is enabled for
+    client_id: String,
+    team_id: String,
+    /// id of the sign in with apple key, the kid of our client secrets
+    key_id: String,
+    /// the .p8 key downloaded from apple, a PKCS#8 PEM
+    private_key_path: String,
+    callback_url: String,
+    #[serde(default = "default_apple_scope")]
+    scope: String,
+    #[serde(default = "default_jwks_cache_seconds")]
+    jwks_cache_seconds: i64,
+}
+
+fn default_apple_scope() -> String { "name email".to_string() }
+fn default_jwks_cache_seconds() -> i64 { 24 * 3600 }
+
+/// claims of the ES256 client secret apple wants instead of a static one
+#[derive(Serialize)]
+struct AppleClientSecretClaims<'a> {
+    iss: &'a str,
+    iat: i64,
+    exp: i64,
+    aud: &'a str,
+    sub: &'a str,
+}
+
+#[derive(Deserialize)]
+struct AppleTokenResponse {
+    id_token: String
+}
+
+/// the user form field apple posts to the callback, only on the very first login
+#[derive(Deserialize)]
+struct AppleUser {
+    name: Option<AppleUserName>
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AppleUserName {
+    first_name: Option<String>,
+    last_name: Option<String>,
+}
+
+/// sign in with apple. apple answers with form_post and has no userinfo endpoint,
+/// the account is read from the id_token which get_access_token hands on in place of an access token
+pub struct AppleAuthenticationService {
+    config: AppleConfiguration,
+    signing_key: EncodingKey,
+    jwks: JwksCache,
+}
+
+impl AppleAuthenticationService {
+    /// None without ./apple_configuration.json, sign in with apple is then not offered
+    pub fn load() -> Option<Self> {
+        let config: AppleConfiguration = load_configuration("./apple_configuration.json")?;
+        let jwks = JwksCache::new(Box::new(HttpJwksSource::new(APPLE_JWKS_URI)), config.jwks_cache_seconds);
+        Some(AppleAuthenticationService::new(config, jwks).unwrap_or_else(|err| {
+            eprintln!("error loading apple key {}", err);
+            process::exit(1);
+        }))
+    }
+
+    pub fn new(config: AppleConfiguration, jwks: JwksCache) -> Result<Self, String> {
+        let pem = fs::read(&config.private_key_path).map_err(|err| format!("{}: {}", config.private_key_path, err))?;
+        let signing_key = EncodingKey::from_ec_pem(&pem).map_err(|err| format!("{}: {}", config.private_key_path, err))?;
+        Ok(AppleAuthenticationService {
+            config,
+            signing_key,
+            jwks,
+        })
+    }
+
+    fn client_secret(&self) -> String {
+        let mut header = Header::new(Algorithm::ES256);
+        header.kid = Some(self.config.key_id.clone());
+        let now = Utc::now().timestamp();
+        let claims = AppleClientSecretClaims {
+            iss: &self.config.team_id,
+            iat: now,
+            exp: now + CLIENT_SECRET_TTL_SECONDS,
+            aud: APPLE_ISSUER,
+            sub: &self.config.client_id,
+        };
+        encode(&header, &claims, &self.signing_key).unwrap()
+    }
+}
+
+#[async_trait]
+impl BaseOAuth20Service for AppleAuthenticationService {
+    type ExternalAccount = ExternalAccount;
+
+    /// apple does not take PKCE, the confidential client secret protects the code
+    fn get_authorization_url(&self, state: &str, _code_challenge: &str) -> String {
+        let mut url = Url::parse(APPLE_AUTHORIZATION_ENDPOINT).unwrap();
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            // apple posts back as soon as name or email are asked for
+            .append_pair("response_mode", "form_post")
+            .append_pair("client_id", &self.config.client_id)
+            .append_pair("redirect_uri", &self.config.callback_url)
+            .append_pair("scope", &self.config.scope)
+            .append_pair("state", state);
+        url.to_string()
+    }
+
+    /// the id_token of the code, apple's access token opens no api of interest to us
+    async fn get_access_token(&self, code: &String, _code_verifier: &str) -> Option<String> {
+        let client_secret = self.client_secret();
+        let endpoint = TokenEndpoint {
+            provider: "apple",
+            url: APPLE_TOKEN_ENDPOINT,
+            client_id: &self.config.client_id,
+            client_auth: ClientAuthentication::Form(&client_secret),
+            redirect_uri: &self.config.callback_url,
+        };
+        endpoint.exchange_code::<AppleTokenResponse>(code, None).await
+            .map(|result| result.id_token)
+    }
+
+    /// the account of a verified id_token. names are only known from the first login form,
+    /// see first_login_names
+    async fn get_account_details(&self, id_token: &String) -> Option<Self::ExternalAccount> {
+        let claims = self.jwks.verify_id_token(id_token, &[APPLE_ISSUER], &self.config.client_id).await?;
+        if !claims.email_verified {
+            info!("apple account {} without a verified email", claims.sub);
+            return None;
+        }
+        Some(ExternalAccount {
+            subject: claims.sub,
+            first_name: None,
+            last_name: None,
+            email: claims.email?,
+            email_verified: claims.email_verified,
+            access_token: None,
+            private_email: claims.is_private_email,
+        })
+    }
+}
+
+/// fill in the names apple posts once, on the first login of the user, as the user form field
+pub fn first_login_names(account: &mut ExternalAccount, user: &str) {
+    let name = match serde_json::from_str::<AppleUser>(user) {
+        Ok(AppleUser { name: Some(name) }) => name,
+        Ok(_) => return,
+        Err(err) => {
+            error!("apple user not understood = {}", err);
+            return;
+        }
+    };
+    account.first_name = account.first_name.take().or(name.first_name);
+    account.last_name = account.last_name.take().or(name.last_name);
+}
+
+#[cfg(test)]
+mod test {
+    use jsonwebtoken::{decode, decode_header, DecodingKey, Validation};
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::nid::Nid;
+    use openssl::pkey::PKey;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::ouath::jwks::testing::{test_cache, TestProvider};
+
+    #[actix_rt::test]
+    async fn test_apple_login() {
+        let key = PKey::from_ec_key(EcKey::generate(&EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap()).unwrap()).unwrap();
+        let private_key_path = std::env::temp_dir().join(format!("apple-{}.p8", uuid::Uuid::new_v4())).to_str().unwrap().to_string();
+        fs::write(&private_key_path, key.private_key_to_pem_pkcs8().unwrap()).unwrap();
+        let config: AppleConfiguration = serde_json::from_value(json!({
+            "client_id": "com.infotamia.iot", "team_id": "TEAM123456", "key_id": "KEY1234567",
+            "private_key_path": private_key_path, "callback_url": "https://infotamia.com/iot/auth2/apple/callback"
+        })).unwrap();
+        let provider = TestProvider::new("apple-1");
+        let (jwks, _) = test_cache(&provider);
+        let service = AppleAuthenticationService::new(config, jwks).unwrap();
+
+        let client_secret = service.client_secret();
+        assert_eq!(decode_header(&client_secret).unwrap().kid.as_deref(), Some("KEY1234567"));
+        let public_pem = key.public_key_to_pem().unwrap();
+        let mut validation = Validation::new(Algorithm::ES256);
+        validation.set_audience(&[APPLE_ISSUER]);
+        let claims = decode::<Value>(&client_secret, &DecodingKey::from_ec_pem(&public_pem).unwrap(), &validation).unwrap().claims;
+        assert_eq!(claims["iss"], "TEAM123456");
+        assert_eq!(claims["sub"], "com.infotamia.iot");
+
+        let url = service.get_authorization_url("state", "challenge");
+        assert!(url.contains("response_mode=form_post"));
+
+        let exp = Utc::now().timestamp() + 300;
+        let id_token = provider.id_token(json!({
+            "iss": APPLE_ISSUER, "aud": "com.infotamia.iot", "exp": exp, "sub": "001.apple",
+            "email": "x7k2@privaterelay.appleid.com", "email_verified": "true", "is_private_email": "true"
+        }));
+        let mut account = service.get_account_details(&id_token).await.unwrap();
+        assert_eq!(account.subject, "001.apple");
+        assert_eq!(account.email, "x7k2@privaterelay.appleid.com");
+        assert!(account.private_email);
+        assert_eq!(account.first_name, None);
+        first_login_names(&mut account, r#"{"name":{"firstName":"Moe","lastName":"Al-Ani"},"email":"x7k2@privaterelay.appleid.com"}"#);
+        assert_eq!(account.first_name.as_deref(), Some("Moe"));
+        assert_eq!(account.last_name.as_deref(), Some("Al-Ani"));
+
+        let unverified = provider.id_token(json!({
+            "iss": APPLE_ISSUER, "aud": "com.infotamia.iot", "exp": exp, "sub": "002.apple",
+            "email": "moe@gmail.com", "email_verified": "false"
+        }));
+        assert!(service.get_account_details(&unverified).await.is_none());
+    }
+}
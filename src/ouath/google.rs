@@ -0,0 +1,142 @@
+use async_trait::async_trait;
+use log::info;
+use reqwest::Url;
+use serde::Deserialize;
+
+use crate::ouath::jwks::{HttpJwksSource, JwksCache};
+use crate::ouath::oauth::{BaseOAuth20Service, ExternalAccount};
+use crate::ouath::provider::{ClientAuthentication, load_configuration, TokenEndpoint};
+
+/// google signs with either spelling of its issuer
+const GOOGLE_ISSUERS: [&str; 2] = ["https://accounts.google.com", "accounts.google.com"];
+const GOOGLE_AUTHORIZATION_ENDPOINT: &str = "https://accounts.google.com/o/oauth2/v2/auth";
+const GOOGLE_TOKEN_ENDPOINT: &str = "https://oauth2.googleapis.com/token";
+const GOOGLE_JWKS_URI: &str = "https://www.googleapis.com/oauth2/v3/certs";
+
+#[derive(Deserialize, Debug)]
+pub struct GoogleConfiguration {
+    client_id: String,
+    client_secret: String,
+    callback_url: String,
+    #[serde(default = "default_google_scope")]
+    scope: String,
+    #[serde(default = "default_jwks_cache_seconds")]
+    jwks_cache_seconds: i64,
+}
+
+fn default_google_scope() -> String { "openid email profile".to_string() }
+fn default_jwks_cache_seconds() -> i64 { 3600 }
+
+#[derive(Deserialize)]
+struct GoogleTokenResponse {
+    id_token: String
+}
+
+/// google sign in over OIDC, the account is read from the id_token which
+/// get_access_token hands on in place of an access token
+pub struct GoogleAuthenticationService {
+    config: GoogleConfiguration,
+    jwks: JwksCache,
+}
+
+impl GoogleAuthenticationService {
+    /// None without ./google_configuration.json, google logins are then not offered
+    pub fn load() -> Option<Self> {
+        let config: GoogleConfiguration = load_configuration("./google_configuration.json")?;
+        let jwks = JwksCache::new(Box::new(HttpJwksSource::new(GOOGLE_JWKS_URI)), config.jwks_cache_seconds);
+        Some(GoogleAuthenticationService::new(config, jwks))
+    }
+
+    pub fn new(config: GoogleConfiguration, jwks: JwksCache) -> Self {
+        GoogleAuthenticationService {
+            config,
+            jwks,
+        }
+    }
+}
+
+#[async_trait]
+impl BaseOAuth20Service for GoogleAuthenticationService {
+    type ExternalAccount = ExternalAccount;
+
+    fn get_authorization_url(&self, state: &str, code_challenge: &str) -> String {
+        let mut url = Url::parse(GOOGLE_AUTHORIZATION_ENDPOINT).unwrap();
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.config.client_id)
+            .append_pair("redirect_uri", &self.config.callback_url)
+            .append_pair("scope", &self.config.scope)
+            .append_pair("code_challenge", code_challenge)
+            .append_pair("code_challenge_method", "S256")
+            .append_pair("state", state);
+        url.to_string()
+    }
+
+    /// the id_token of the code
+    async fn get_access_token(&self, code: &String, code_verifier: &str) -> Option<String> {
+        let endpoint = TokenEndpoint {
+            provider: "google",
+            url: GOOGLE_TOKEN_ENDPOINT,
+            client_id: &self.config.client_id,
+            client_auth: ClientAuthentication::Form(&self.config.client_secret),
+            redirect_uri: &self.config.callback_url,
+        };
+        endpoint.exchange_code::<GoogleTokenResponse>(code, Some(code_verifier)).await
+            .map(|result| result.id_token)
+    }
+
+    async fn get_account_details(&self, id_token: &String) -> Option<Self::ExternalAccount> {
+        let claims = self.jwks.verify_id_token(id_token, &GOOGLE_ISSUERS, &self.config.client_id).await?;
+        if !claims.email_verified {
+            info!("google account {} without a verified email", claims.sub);
+            return None;
+        }
+        Some(ExternalAccount {
+            subject: claims.sub,
+            first_name: claims.given_name,
+            last_name: claims.family_name,
+            email: claims.email?,
+            email_verified: claims.email_verified,
+            access_token: None,
+            private_email: false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Utc;
+    use serde_json::json;
+
+    use super::*;
+    use crate::ouath::jwks::testing::{test_cache, TestProvider};
+
+    #[actix_rt::test]
+    async fn test_google_account() {
+        let config: GoogleConfiguration = serde_json::from_value(json!({
+            "client_id": "iot.apps.googleusercontent.com", "client_secret": "secret",
+            "callback_url": "https://infotamia.com/iot/auth2/google/callback"
+        })).unwrap();
+        let provider = TestProvider::new("google-1");
+        let (jwks, _) = test_cache(&provider);
+        let service = GoogleAuthenticationService::new(config, jwks);
+
+        let exp = Utc::now().timestamp() + 300;
+        let id_token = provider.id_token(json!({
+            "iss": "accounts.google.com", "aud": "iot.apps.googleusercontent.com", "exp": exp, "sub": "1077",
+            "email": "moe@gmail.com", "email_verified": true, "given_name": "Moe", "family_name": "Al-Ani"
+        }));
+        let account = service.get_account_details(&id_token).await.unwrap();
+        assert_eq!(account.subject, "1077");
+        assert_eq!(account.email, "moe@gmail.com");
+        assert!(account.email_verified);
+        assert_eq!(account.first_name.as_deref(), Some("Moe"));
+        assert_eq!(account.last_name.as_deref(), Some("Al-Ani"));
+
+        let unverified = provider.id_token(json!({
+            "iss": "https://accounts.google.com", "aud": "iot.apps.googleusercontent.com", "exp": exp, "sub": "1078",
+            "email": "moe@infotamia.com", "email_verified": false
+        }));
+        assert!(service.get_account_details(&unverified).await.is_none());
+    }
+}
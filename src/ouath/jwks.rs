@@ -0,0 +1,254 @@
+use std::sync::RwLock;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use jsonwebtoken::{Algorithm, decode, decode_header, DecodingKey, Validation};
+use log::error;
+use serde::{Deserialize, Deserializer};
+
+use crate::entities::jwk_entities::JwkSet;
+
+/// a cached set is fetched again this soon at the earliest when an unknown kid shows up
+const MIN_REFRESH_SECONDS: i64 = 60;
+
+/// where the signing keys of a provider come from, the https jwks_uri outside of tests
+#[async_trait]
+pub trait JwksSource {
+    async fn fetch(&self) -> Option<JwkSet>;
+}
+
+pub struct HttpJwksSource {
+    jwks_uri: String
+}
+
+impl HttpJwksSource {
+    pub fn new(jwks_uri: &str) -> Self {
+        HttpJwksSource {
+            jwks_uri: jwks_uri.to_string()
+        }
+    }
+}
+
+#[async_trait]
+impl JwksSource for HttpJwksSource {
+    async fn fetch(&self) -> Option<JwkSet> {
+        let response = reqwest::get(&self.jwks_uri).await;
+        match response {
+            Ok(res) if res.status().is_success() => {
+                let data = res.text().await.ok()?;
+                serde_json::from_str(&data).map_err(|err| error!("jwks of {} not understood = {}", self.jwks_uri, err)).ok()
+            }
+            Ok(res) => {
+                error!("jwks of {} answered {}", self.jwks_uri, res.status());
+                None
+            }
+            Err(err) => {
+                error!("error = {}", err);
+                None
+            }
+        }
+    }
+}
+
+struct CachedKeys {
+    fetched_at: i64,
+    keys: JwkSet,
+}
+
+/// signing keys of a provider, kept for ttl_seconds and fetched again early when the
+/// provider rotated to a kid we have not seen yet
+pub struct JwksCache {
+    source: Box<dyn JwksSource + Send + Sync>,
+    ttl_seconds: i64,
+    cached: RwLock<Option<CachedKeys>>,
+}
+
+impl JwksCache {
+    pub fn new(source: Box<dyn JwksSource + Send + Sync>, ttl_seconds: i64) -> Self {
+        JwksCache {
+            source,
+            ttl_seconds,
+            cached: RwLock::new(None),
+        }
+    }
+
+    /// RSA verification key of the kid, None when the provider does not publish it
+    pub async fn decoding_key(&self, kid: &str) -> Option<(DecodingKey<'static>, Algorithm)> {
+        let now = Utc::now().timestamp();
+        let refetch = match *self.cached.read().unwrap() {
+            None => true,
+            Some(ref cached) => {
+                let age = now - cached.fetched_at;
+                age >= self.ttl_seconds || (find_key(&cached.keys, kid).is_none() && age >= MIN_REFRESH_SECONDS)
+            }
+        };
+        if refetch {
+            if let Some(keys) = self.source.fetch().await {
+                *self.cached.write().unwrap() = Some(CachedKeys { fetched_at: now, keys });
+            }
+        }
+        let cached = self.cached.read().unwrap();
+        find_key(&cached.as_ref()?.keys, kid)
+    }
+
+    /// claims of an id_token signed by the provider for the audience, None when the signature,
+    /// issuer, audience or expiry do not check out
+    pub async fn verify_id_token(&self, id_token: &str, issuers: &[&str], audience: &str) -> Option<ProviderIdToken> {
+        let header = decode_header(id_token).ok()?;
+        let (key, algorithm) = self.decoding_key(header.kid.as_deref()?).await?;
+        let mut validation = Validation::new(algorithm);
+        validation.set_audience(&[audience]);
+        let claims = decode::<ProviderIdToken>(id_token, &key, &validation)
+            .map_err(|err| error!("id_token rejected = {}", err))
+            .ok()?.claims;
+        if issuers.contains(&claims.iss.as_str()) {
+            Some(claims)
+        } else {
+            error!("id_token of unexpected issuer {}", claims.iss);
+            None
+        }
+    }
+}
+
+fn find_key(keys: &JwkSet, kid: &str) -> Option<(DecodingKey<'static>, Algorithm)> {
+    let jwk = keys.keys.iter().find(|jwk| jwk.kid == kid && jwk.kty == "RSA")?;
+    let algorithm = match jwk.alg.as_str() {
+        "RS384" => Algorithm::RS384,
+        "RS512" => Algorithm::RS512,
+        _ => Algorithm::RS256
+    };
+    let key = DecodingKey::from_rsa_components(jwk.n.as_ref()?, jwk.e.as_ref()?).into_static();
+    Some((key, algorithm))
+}
+
+/// the OIDC claims we read from the id_token of a social login
+#[derive(Deserialize, Debug)]
+pub struct ProviderIdToken {
+    pub iss: String,
+    pub sub: String,
+    pub email: Option<String>,
+    #[serde(default, deserialize_with = "flexible_bool")]
+    pub email_verified: bool,
+    /// apple only, the email is an address of its private relay
+    #[serde(default, deserialize_with = "flexible_bool")]
+    pub is_private_email: bool,
+    pub given_name: Option<String>,
+    pub family_name: Option<String>,
+}
+
+/// apple sends the boolean claims as "true" and "false"
+fn flexible_bool<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Flexible {
+        Bool(bool),
+        Text(String),
+    }
+    Ok(match Flexible::deserialize(deserializer)? {
+        Flexible::Bool(value) => value,
+        Flexible::Text(value) => value == "true",
+    })
+}
+
+/// a fake provider for the tests of the social logins
+#[cfg(test)]
+pub mod testing {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use openssl::pkey::Private;
+    use openssl::rsa::Rsa;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::entities::jwk_entities::Jwk;
+
+    /// an RSA key of a fake provider, signs id_tokens and publishes itself as a jwks
+    pub struct TestProvider {
+        kid: String,
+        rsa: Rsa<Private>,
+    }
+
+    impl TestProvider {
+        pub fn new(kid: &str) -> Self {
+            TestProvider { kid: kid.to_string(), rsa: Rsa::generate(2048).unwrap() }
+        }
+
+        pub fn jwks(&self) -> JwkSet {
+            let encode = |bytes: Vec<u8>| base64::encode_config(bytes, base64::URL_SAFE_NO_PAD);
+            JwkSet {
+                keys: vec![Jwk {
+                    kty: "RSA".to_string(),
+                    kid: self.kid.clone(),
+                    alg: "RS256".to_string(),
+                    key_use: "sig".to_string(),
+                    n: Some(encode(self.rsa.n().to_vec())),
+                    e: Some(encode(self.rsa.e().to_vec())),
+                    crv: None,
+                    x: None,
+                    y: None,
+                }]
+            }
+        }
+
+        pub fn id_token(&self, claims: Value) -> String {
+            let mut header = Header::new(Algorithm::RS256);
+            header.kid = Some(self.kid.clone());
+            let key = EncodingKey::from_rsa_pem(&self.rsa.private_key_to_pem().unwrap()).unwrap();
+            encode(&header, &claims, &key).unwrap()
+        }
+    }
+
+    /// answers with a fixed key set and counts how often it was asked
+    pub struct StaticJwksSource {
+        pub keys: Value,
+        pub fetches: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl JwksSource for StaticJwksSource {
+        async fn fetch(&self) -> Option<JwkSet> {
+            self.fetches.fetch_add(1, Ordering::SeqCst);
+            serde_json::from_value(self.keys.clone()).ok()
+        }
+    }
+
+    pub fn test_cache(provider: &TestProvider) -> (JwksCache, Arc<AtomicUsize>) {
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let source = StaticJwksSource { keys: serde_json::to_value(provider.jwks()).unwrap(), fetches: fetches.clone() };
+        (JwksCache::new(Box::new(source), 3600), fetches)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::Ordering;
+
+    use serde_json::json;
+
+    use super::*;
+    use super::testing::{test_cache, TestProvider};
+
+    #[actix_rt::test]
+    async fn test_verify_id_token() {
+        let provider = TestProvider::new("k1");
+        let (cache, fetches) = test_cache(&provider);
+        let exp = Utc::now().timestamp() + 300;
+        let id_token = provider.id_token(json!({
+            "iss": "https://appleid.apple.com", "aud": "com.infotamia.iot", "exp": exp, "sub": "001.apple",
+            "email": "moe@privaterelay.appleid.com", "email_verified": "true", "is_private_email": "true"
+        }));
+        let claims = cache.verify_id_token(&id_token, &["https://appleid.apple.com"], "com.infotamia.iot").await.unwrap();
+        assert_eq!(claims.sub, "001.apple");
+        assert!(claims.email_verified && claims.is_private_email);
+
+        assert!(cache.verify_id_token(&id_token, &["https://appleid.apple.com"], "com.infotamia.other").await.is_none());
+        assert!(cache.verify_id_token(&id_token, &["https://accounts.google.com"], "com.infotamia.iot").await.is_none());
+        // signed by a key the provider does not publish
+        let stranger = TestProvider::new("k2").id_token(json!({"iss": "https://appleid.apple.com", "aud": "com.infotamia.iot", "exp": exp, "sub": "001.apple"}));
+        assert!(cache.verify_id_token(&stranger, &["https://appleid.apple.com"], "com.infotamia.iot").await.is_none());
+        // fetched once, the unknown kid does not refetch a set this young
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+    }
+}
@@ -1 +1,6 @@
+pub mod apple;
+pub mod google;
+pub mod jwks;
 pub mod oauth;
+pub mod provider;
+pub mod twitter;
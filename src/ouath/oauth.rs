@@ -13,15 +13,28 @@ use reqwest::{Url, Response, Error};
 use openssl::ssl::{SslConnector, SslMethod};
 use async_trait::async_trait;
 use log::error;
-use crate::services::jwt_service::{JwtClaims, SessionType, issue};
-use chrono::{Utc, Duration};
-use std::ops::Add;
+use crate::entities::authorization_entities::PendingAuthorization;
+
+/// what a social login has to remember across the provider redirect, kept in the login_state table
+pub struct LoginState {
+    /// ends up in the id_token
+    pub nonce: Option<String>,
+    pub scope: String,
+    /// set when the login answers an authorization request of one of our clients
+    pub authorization: Option<PendingAuthorization>,
+    /// set when a signed in user links the provider account instead of logging in with it
+    pub link_user_id: Option<u32>,
+}
 
 #[async_trait]
 pub trait BaseOAuth20Service {
     type ExternalAccount;
-    fn get_authorization_url(&self) -> String;
-    async fn get_access_token(&self, code: &String) -> String;
+    /// state is the one time login state the provider sends back to the callback,
+    /// code_challenge the S256 challenge of the login's PKCE verifier
+    fn get_authorization_url(&self, state: &str, code_challenge: &str) -> String;
+    /// code_verifier is the PKCE verifier of the login, providers without PKCE leave it out
+    /// None when the provider does not hand out a token for the code
+    async fn get_access_token(&self, code: &String, code_verifier: &str) -> Option<String>;
     async fn get_account_details(&self, access_token: &String) -> Option<Self::ExternalAccount>;
 }
 
@@ -42,17 +55,17 @@ impl BaseOAuth20Service for FacebookAuthenticationService {
     type ExternalAccount = ExternalAccount;
 
     /// return this to the caller (client)
-    fn get_authorization_url(&self) -> String {
+    fn get_authorization_url(&self, state: &str, _code_challenge: &str) -> String {
         /// fbauth.getauthurl
         FacebookOAuth20Builder::new(&self.config.client_secret, &self.config.client_id)
             .scope("email".to_string())
             .redirect_url(self.config.callback_url.clone())
-            .state(generate_state())
+            .state(state.to_string())
             .build_step1()
     }
 
     /// fetch auth token by code
-    async fn get_access_token(&self, code: &String) -> String {
+    async fn get_access_token(&self, code: &String, _code_verifier: &str) -> Option<String> {
         let url = FacebookOAuth20Builder::new(&self.config.client_secret, &self.config.client_id)
             .scope("email".to_string())
             .redirect_url(self.config.callback_url.clone())
@@ -63,11 +76,11 @@ impl BaseOAuth20Service for FacebookAuthenticationService {
             Ok(res) => {
                 let data = res.text().await.unwrap();
                 let result: FacebookAccessTokenResponse = serde_json::from_str(&data).unwrap();
-                result.access_token
+                Some(result.access_token)
             }
             Err(err) => {
                 error!("error = {}", err);
-                "".to_string()
+                None
             }
         }
     }
@@ -84,6 +97,8 @@ impl BaseOAuth20Service for FacebookAuthenticationService {
                 let data = res.text().await.unwrap();
                 let mut result: ExternalAccount = serde_json::from_str(&data).unwrap();
                 result.access_token = Some(access_token.clone());
+                // facebook leaves the email out unless it was confirmed
+                result.email_verified = true;
                 Some(result)
             }
             Err(err) => {
@@ -102,19 +117,31 @@ struct FacebookAccessTokenResponse {
 
 #[derive(Deserialize)]
 pub struct ExternalAccount {
+    /// the provider's id of the account, it stays when the email changes. the id of facebook's profile
+    #[serde(rename = "id")]
+    pub subject: String,
     pub first_name: Option<String>,
     pub last_name: Option<String>,
     pub email: String,
+    /// the provider vouches the email belongs to the account, only then may it sign up or be linked
+    #[serde(default)]
+    pub email_verified: bool,
     pub access_token: Option<String>,
+    /// the email is a relay address the provider forwards from, apple's hide my email
+    #[serde(default)]
+    pub private_email: bool,
 }
 
 impl ExternalAccount {
     pub fn new() -> Self {
         ExternalAccount {
+            subject: "".to_string(),
             first_name: None,
             last_name: None,
             email: "".to_string(),
-            access_token: Some("".to_string())
+            email_verified: false,
+            access_token: Some("".to_string()),
+            private_email: false
         }
     }
 }
@@ -243,21 +270,6 @@ impl <'a> FacebookOAuth20Builder<'a> {
     }
 }
 
-fn generate_state() -> String {
-    let mut claims = JwtClaims {
-        aud: None,
-        exp: Utc::now().add(Duration::minutes(1)).timestamp() as usize,
-        iat: Utc::now().timestamp() as usize,
-        issuer: Some("infotamia.com".to_string()),
-        jwt_id: Some(uuid::Uuid::new_v4().to_string()),
-        sub: Some(uuid::Uuid::new_v4().to_string()),
-        access_token: None,
-        session_type: None,
-    };
-
-    issue(&mut claims)
-}
-
 #[cfg(test)]
 mod test {
     use super::*;
@@ -265,7 +277,7 @@ mod test {
     #[test]
     fn test_auth_service() {
         let service = FacebookAuthenticationService::new();
-        let url = service.get_authorization_url();
+        let url = service.get_authorization_url("state", "challenge");
         println!("url = {}", url)
     }
 
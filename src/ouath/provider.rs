@@ -0,0 +1,375 @@
+use std::{fs, process};
+use std::collections::HashMap;
+use std::io::ErrorKind;
+
+use async_trait::async_trait;
+use log::{error, info};
+use reqwest::Url;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::ouath::apple::AppleAuthenticationService;
+use crate::ouath::google::GoogleAuthenticationService;
+use crate::ouath::oauth::{BaseOAuth20Service, ExternalAccount, FacebookAuthenticationService};
+use crate::ouath::twitter::TwitterAuthenticationService;
+use crate::services::jwt_service::AuthenticationProvider;
+
+/// a social login the callback can be dispatched to by name
+pub type OAuthProvider = dyn BaseOAuth20Service<ExternalAccount=ExternalAccount> + Send + Sync;
+
+/// an OAuth 2.0 or OIDC provider speaking the standard code flow, one entry of oauth_providers.json
+#[derive(Deserialize, Debug, Clone)]
+pub struct ProviderConfiguration {
+    /// the {provider} of /iot/auth2/{provider}/login1
+    pub name: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub userinfo_endpoint: String,
+    /// space delimited scopes asked of the provider
+    pub scope: String,
+    pub callback_url: String,
+    #[serde(default)]
+    pub claims: ClaimMapping,
+}
+
+/// where the userinfo response keeps the account details, dotted for nested objects
+#[derive(Deserialize, Debug, Clone)]
+pub struct ClaimMapping {
+    #[serde(default = "default_subject_claim")]
+    pub subject: String,
+    #[serde(default = "default_email_claim")]
+    pub email: String,
+    /// a boolean or "true", accounts without it cannot sign up or be linked
+    #[serde(default = "default_email_verified_claim")]
+    pub email_verified: String,
+    #[serde(default = "default_first_name_claim")]
+    pub first_name: String,
+    #[serde(default = "default_last_name_claim")]
+    pub last_name: String,
+}
+
+fn default_subject_claim() -> String { "sub".to_string() }
+fn default_email_claim() -> String { "email".to_string() }
+fn default_email_verified_claim() -> String { "email_verified".to_string() }
+fn default_first_name_claim() -> String { "given_name".to_string() }
+fn default_last_name_claim() -> String { "family_name".to_string() }
+
+/// the OIDC standard claims
+impl Default for ClaimMapping {
+    fn default() -> Self {
+        ClaimMapping {
+            subject: default_subject_claim(),
+            email: default_email_claim(),
+            email_verified: default_email_verified_claim(),
+            first_name: default_first_name_claim(),
+            last_name: default_last_name_claim(),
+        }
+    }
+}
+
+impl ClaimMapping {
+    /// the account a userinfo response describes, None without a subject or an email
+    fn to_account(&self, userinfo: &Value, access_token: &str) -> Option<ExternalAccount> {
+        let email_verified = match lookup(userinfo, &self.email_verified) {
+            Some(Value::Bool(verified)) => *verified,
+            Some(Value::String(verified)) => verified == "true",
+            _ => false
+        };
+        Some(ExternalAccount {
+            subject: claim(userinfo, &self.subject)?,
+            first_name: claim(userinfo, &self.first_name),
+            last_name: claim(userinfo, &self.last_name),
+            email: claim(userinfo, &self.email)?,
+            email_verified,
+            access_token: Some(access_token.to_string()),
+            private_email: false,
+        })
+    }
+}
+
+fn lookup<'a>(userinfo: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(userinfo, |value, key| value.get(key))
+}
+
+/// a text claim, numbers are taken as text for the numeric account ids of some providers
+fn claim(userinfo: &Value, path: &str) -> Option<String> {
+    match lookup(userinfo, path)? {
+        Value::String(value) => Some(value.clone()),
+        Value::Number(value) => Some(value.to_string()),
+        _ => None
+    }
+}
+
+/// how a client authenticates at a token endpoint, RFC 6749 2.3.1
+pub enum ClientAuthentication<'a> {
+    /// client_secret in the form body
+    Form(&'a str),
+    /// the id and the secret as basic credentials
+    Basic(&'a str),
+}
+
+/// the token endpoint of a provider and what our client sends to every request of it
+pub struct TokenEndpoint<'a> {
+    /// names the provider in the logs
+    pub provider: &'a str,
+    pub url: &'a str,
+    pub client_id: &'a str,
+    pub client_auth: ClientAuthentication<'a>,
+    pub redirect_uri: &'a str,
+}
+
+impl TokenEndpoint<'_> {
+    /// RFC 6749 4.1.3, the token response of the code. None when the provider cannot be reached
+    /// or its answer is not understood. providers without PKCE get no code_verifier
+    pub async fn exchange_code<T: DeserializeOwned>(&self, code: &str, code_verifier: Option<&str>) -> Option<T> {
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.redirect_uri),
+            ("client_id", self.client_id),
+        ];
+        if let ClientAuthentication::Form(client_secret) = self.client_auth {
+            form.push(("client_secret", client_secret));
+        }
+        if let Some(code_verifier) = code_verifier {
+            form.push(("code_verifier", code_verifier));
+        }
+        let mut request = reqwest::Client::new()
+            .post(self.url)
+            .header("Accept", "application/json");
+        if let ClientAuthentication::Basic(client_secret) = self.client_auth {
+            request = request.basic_auth(self.client_id, Some(client_secret));
+        }
+        let response = request.form(&form).send().await;
+        match response {
+            Ok(res) => {
+                let data = res.text().await.unwrap_or_default();
+                serde_json::from_str::<T>(&data)
+                    .map_err(|err| error!("{} token response not understood = {}", self.provider, err))
+                    .ok()
+            }
+            Err(err) => {
+                error!("error = {}", err);
+                None
+            }
+        }
+    }
+}
+
+pub struct GenericOAuth20Service {
+    config: ProviderConfiguration
+}
+
+impl GenericOAuth20Service {
+    pub fn new(config: ProviderConfiguration) -> Self {
+        GenericOAuth20Service {
+            config
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ProviderAccessTokenResponse {
+    access_token: String
+}
+
+#[async_trait]
+impl BaseOAuth20Service for GenericOAuth20Service {
+    type ExternalAccount = ExternalAccount;
+
+    /// RFC 7636, providers without PKCE ignore the challenge
+    fn get_authorization_url(&self, state: &str, code_challenge: &str) -> String {
+        let mut url = Url::parse(&self.config.authorization_endpoint).unwrap();
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.config.client_id)
+            .append_pair("redirect_uri", &self.config.callback_url)
+            .append_pair("scope", &self.config.scope)
+            .append_pair("code_challenge", code_challenge)
+            .append_pair("code_challenge_method", "S256")
+            .append_pair("state", state);
+        url.to_string()
+    }
+
+    /// the client authenticates with its secret in the form body
+    async fn get_access_token(&self, code: &String, code_verifier: &str) -> Option<String> {
+        let endpoint = TokenEndpoint {
+            provider: &self.config.name,
+            url: &self.config.token_endpoint,
+            client_id: &self.config.client_id,
+            client_auth: ClientAuthentication::Form(&self.config.client_secret),
+            redirect_uri: &self.config.callback_url,
+        };
+        endpoint.exchange_code::<ProviderAccessTokenResponse>(code, Some(code_verifier)).await
+            .map(|result| result.access_token)
+    }
+
+    async fn get_account_details(&self, access_token: &String) -> Option<Self::ExternalAccount> {
+        let response = reqwest::Client::new()
+            .get(&self.config.userinfo_endpoint)
+            .header("Accept", "application/json")
+            .bearer_auth(access_token)
+            .send().await;
+        match response {
+            Ok(res) if res.status().is_success() => {
+                let data = res.text().await.ok()?;
+                let userinfo: Value = serde_json::from_str(&data).ok()?;
+                self.config.claims.to_account(&userinfo, access_token)
+            }
+            Ok(res) => {
+                error!("{} userinfo answered {}", self.config.name, res.status());
+                None
+            }
+            Err(err) => {
+                error!("error = {}", err);
+                None
+            }
+        }
+    }
+}
+
+/// configuration of an optional provider, None when the file does not exist.
+/// a file that cannot be read or deserialized stops the service like the other configurations
+pub fn load_configuration<T: DeserializeOwned>(path: &str) -> Option<T> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == ErrorKind::NotFound => return None,
+        Err(err) => {
+            eprintln!("error reading file {}", err);
+            process::exit(1);
+        }
+    };
+    Some(serde_json::from_str(content.as_str()).unwrap_or_else(|err| {
+        eprintln!("error deserializing file content {}", err);
+        process::exit(1);
+    }))
+}
+
+/// every social login, looked up by the provider name of the login routes
+pub struct OAuthProviders {
+    providers: HashMap<String, Box<OAuthProvider>>
+}
+
+impl OAuthProviders {
+    /// the providers of ./oauth_providers.json, facebook and apple, google and twitter when configured.
+    /// an entry of the same name replaces a built-in provider, the file is optional
+    pub fn new() -> Self {
+        let configurations = load_configuration("./oauth_providers.json").unwrap_or_else(|| {
+            info!("no oauth_providers.json, only the built-in providers are offered");
+            vec![]
+        });
+        let mut providers = OAuthProviders::from_configurations(configurations).unwrap_or_else(|err| {
+            eprintln!("error in oauth_providers.json {}", err);
+            process::exit(1);
+        });
+        let facebook = AuthenticationProvider::FACEBOOK.to_string();
+        if providers.get(&facebook).is_none() {
+            providers.register(&facebook, Box::new(FacebookAuthenticationService::new()));
+        }
+        if providers.get(&AuthenticationProvider::APPLE.to_string()).is_none() {
+            if let Some(service) = AppleAuthenticationService::load() {
+                providers.register(&AuthenticationProvider::APPLE.to_string(), Box::new(service));
+            }
+        }
+        if providers.get(&AuthenticationProvider::GOOGLE.to_string()).is_none() {
+            if let Some(service) = GoogleAuthenticationService::load() {
+                providers.register(&AuthenticationProvider::GOOGLE.to_string(), Box::new(service));
+            }
+        }
+        if providers.get(&AuthenticationProvider::TWITTER.to_string()).is_none() {
+            if let Some(service) = TwitterAuthenticationService::load() {
+                providers.register(&AuthenticationProvider::TWITTER.to_string(), Box::new(service));
+            }
+        }
+        providers
+    }
+
+    /// fails on duplicate names and on endpoints that are not urls
+    pub fn from_configurations(configurations: Vec<ProviderConfiguration>) -> Result<Self, String> {
+        let mut providers = OAuthProviders { providers: HashMap::new() };
+        for config in configurations {
+            for endpoint in &[&config.authorization_endpoint, &config.token_endpoint, &config.userinfo_endpoint] {
+                Url::parse(endpoint).map_err(|err| format!("{} endpoint {}: {}", config.name, endpoint, err))?;
+            }
+            if providers.get(&config.name).is_some() {
+                return Err(format!("provider {} configured twice", config.name));
+            }
+            let name = config.name.clone();
+            providers.register(&name, Box::new(GenericOAuth20Service::new(config)));
+        }
+        Ok(providers)
+    }
+
+    pub fn register(&mut self, name: &str, provider: Box<OAuthProvider>) -> &mut Self {
+        self.providers.insert(name.to_string(), provider);
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&OAuthProvider> {
+        self.providers.get(name).map(|provider| provider.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    fn keycloak() -> ProviderConfiguration {
+        serde_json::from_value(json!({
+            "name": "keycloak",
+            "client_id": "iot",
+            "client_secret": "secret",
+            "authorization_endpoint": "https://sso.infotamia.com/auth/realms/iot/protocol/openid-connect/auth",
+            "token_endpoint": "https://sso.infotamia.com/auth/realms/iot/protocol/openid-connect/token",
+            "userinfo_endpoint": "https://sso.infotamia.com/auth/realms/iot/protocol/openid-connect/userinfo",
+            "scope": "openid email profile",
+            "callback_url": "https://infotamia.com/iot/auth2/keycloak/callback"
+        })).unwrap()
+    }
+
+    #[test]
+    fn test_generic_provider() {
+        let providers = OAuthProviders::from_configurations(vec![keycloak()]).unwrap();
+        let url = providers.get("keycloak").unwrap().get_authorization_url("state", "challenge");
+        let url = Url::parse(&url).unwrap();
+        let query: HashMap<_, _> = url.query_pairs().into_owned().collect();
+        assert_eq!(url.path(), "/auth/realms/iot/protocol/openid-connect/auth");
+        assert_eq!(query["response_type"], "code");
+        assert_eq!(query["scope"], "openid email profile");
+        assert_eq!(query["redirect_uri"], "https://infotamia.com/iot/auth2/keycloak/callback");
+        assert_eq!(query["state"], "state");
+        assert_eq!(query["code_challenge"], "challenge");
+
+        assert!(providers.get("github").is_none());
+        assert!(OAuthProviders::from_configurations(vec![keycloak(), keycloak()]).is_err());
+    }
+
+    #[test]
+    fn test_claim_mapping() {
+        let access_token = "token".to_string();
+        let userinfo = json!({"sub": "f3a1", "email": "moe@gmail.com", "email_verified": true, "given_name": "Moe", "family_name": "Al-Ani"});
+        let account = ClaimMapping::default().to_account(&userinfo, &access_token).unwrap();
+        assert_eq!(account.subject, "f3a1");
+        assert_eq!(account.email, "moe@gmail.com");
+        assert!(account.email_verified);
+        assert_eq!(account.first_name.as_deref(), Some("Moe"));
+        assert_eq!(account.last_name.as_deref(), Some("Al-Ani"));
+
+        let mapping = ClaimMapping {
+            subject: "id".to_string(), email: "contact.mail".to_string(), email_verified: "contact.verified".to_string(),
+            first_name: "name".to_string(), last_name: "surname".to_string()
+        };
+        let account = mapping.to_account(&json!({"id": 583231, "contact": {"mail": "moe@gmail.com"}, "name": "Moe"}), &access_token).unwrap();
+        assert_eq!(account.subject, "583231");
+        assert_eq!(account.email, "moe@gmail.com");
+        assert!(!account.email_verified);
+        assert_eq!(account.last_name, None);
+        assert!(mapping.to_account(&json!({"id": 583231, "name": "Moe"}), &access_token).is_none());
+        assert!(mapping.to_account(&json!({"contact": {"mail": "moe@gmail.com"}}), &access_token).is_none());
+    }
+}
@@ -0,0 +1,158 @@
+use async_trait::async_trait;
+use log::error;
+use reqwest::Url;
+use serde::Deserialize;
+
+use crate::ouath::oauth::{BaseOAuth20Service, ExternalAccount};
+use crate::ouath::provider::{ClientAuthentication, load_configuration, TokenEndpoint};
+
+const TWITTER_AUTHORIZATION_ENDPOINT: &str = "https://twitter.com/i/oauth2/authorize";
+const TWITTER_TOKEN_ENDPOINT: &str = "https://api.twitter.com/2/oauth2/token";
+/// confirmed_email needs the users.email scope
+const TWITTER_USER_ENDPOINT: &str = "https://api.twitter.com/2/users/me?user.fields=confirmed_email";
+
+/// a confidential twitter app
+#[derive(Deserialize, Debug)]
+pub struct TwitterConfiguration {
+    client_id: String,
+    client_secret: String,
+    callback_url: String,
+    #[serde(default = "default_twitter_scope")]
+    scope: String,
+}
+
+fn default_twitter_scope() -> String { "users.read users.email tweet.read".to_string() }
+
+#[derive(Deserialize)]
+struct TwitterAccessTokenResponse {
+    access_token: String
+}
+
+#[derive(Deserialize)]
+struct TwitterUserResponse {
+    data: TwitterUser
+}
+
+#[derive(Deserialize)]
+struct TwitterUser {
+    id: String,
+    name: Option<String>,
+    confirmed_email: Option<String>,
+}
+
+/// twitter login over OAuth 2.0 with PKCE
+pub struct TwitterAuthenticationService {
+    config: TwitterConfiguration
+}
+
+impl TwitterAuthenticationService {
+    /// None without ./twitter_configuration.json, twitter logins are then not offered
+    pub fn load() -> Option<Self> {
+        let config: TwitterConfiguration = load_configuration("./twitter_configuration.json")?;
+        Some(TwitterAuthenticationService::new(config))
+    }
+
+    pub fn new(config: TwitterConfiguration) -> Self {
+        TwitterAuthenticationService {
+            config
+        }
+    }
+}
+
+#[async_trait]
+impl BaseOAuth20Service for TwitterAuthenticationService {
+    type ExternalAccount = ExternalAccount;
+
+    fn get_authorization_url(&self, state: &str, code_challenge: &str) -> String {
+        let mut url = Url::parse(TWITTER_AUTHORIZATION_ENDPOINT).unwrap();
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.config.client_id)
+            .append_pair("redirect_uri", &self.config.callback_url)
+            .append_pair("scope", &self.config.scope)
+            .append_pair("code_challenge", code_challenge)
+            .append_pair("code_challenge_method", "S256")
+            .append_pair("state", state);
+        url.to_string()
+    }
+
+    /// confidential clients authenticate with basic credentials, the verifier proves the login is ours
+    async fn get_access_token(&self, code: &String, code_verifier: &str) -> Option<String> {
+        let endpoint = TokenEndpoint {
+            provider: "twitter",
+            url: TWITTER_TOKEN_ENDPOINT,
+            client_id: &self.config.client_id,
+            client_auth: ClientAuthentication::Basic(&self.config.client_secret),
+            redirect_uri: &self.config.callback_url,
+        };
+        endpoint.exchange_code::<TwitterAccessTokenResponse>(code, Some(code_verifier)).await
+            .map(|result| result.access_token)
+    }
+
+    async fn get_account_details(&self, access_token: &String) -> Option<Self::ExternalAccount> {
+        let response = reqwest::Client::new()
+            .get(TWITTER_USER_ENDPOINT)
+            .bearer_auth(access_token)
+            .send().await;
+        match response {
+            Ok(res) if res.status().is_success() => {
+                let data = res.text().await.ok()?;
+                twitter_account(&data, access_token)
+            }
+            Ok(res) => {
+                error!("twitter user answered {}", res.status());
+                None
+            }
+            Err(err) => {
+                error!("error = {}", err);
+                None
+            }
+        }
+    }
+}
+
+/// twitter knows a display name only, it becomes the first name
+fn twitter_account(data: &str, access_token: &str) -> Option<ExternalAccount> {
+    let user = serde_json::from_str::<TwitterUserResponse>(data)
+        .map_err(|err| error!("twitter user not understood = {}", err))
+        .ok()?.data;
+    Some(ExternalAccount {
+        subject: user.id,
+        first_name: user.name,
+        last_name: None,
+        email: user.confirmed_email?,
+        // twitter only hands out confirmed emails
+        email_verified: true,
+        access_token: Some(access_token.to_string()),
+        private_email: false,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_twitter_pkce() {
+        let config: TwitterConfiguration = serde_json::from_value(json!({
+            "client_id": "iot", "client_secret": "secret", "callback_url": "https://infotamia.com/iot/auth2/twitter/callback"
+        })).unwrap();
+        let service = TwitterAuthenticationService::new(config);
+        let url = service.get_authorization_url("state", "challenge");
+        let query: HashMap<_, _> = Url::parse(&url).unwrap().query_pairs().into_owned().collect();
+        assert_eq!(query["state"], "state");
+        assert_eq!(query["code_challenge"], "challenge");
+        assert_eq!(query["code_challenge_method"], "S256");
+
+        let access_token = "token".to_string();
+        let account = twitter_account(r#"{"data":{"id":"2244994945","name":"Moe","username":"moe","confirmed_email":"moe@gmail.com"}}"#, &access_token).unwrap();
+        assert_eq!(account.subject, "2244994945");
+        assert_eq!(account.email, "moe@gmail.com");
+        assert_eq!(account.first_name.as_deref(), Some("Moe"));
+        assert!(twitter_account(r#"{"data":{"id":"2244994945","name":"Moe","username":"moe"}}"#, &access_token).is_none());
+    }
+}
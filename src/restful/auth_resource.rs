@@ -0,0 +1,149 @@
+use actix_web::{get, HttpResponse, post, web};
+use serde::Deserialize;
+use sqlx::MySqlPool;
+
+use crate::entities::token_entities::{LogoutRequest, RevokeUserRequest};
+use crate::exceptions::error_base::{ErrorResponse, HttpErrorCode};
+use crate::filters::authorization_filter::RequirePermission;
+use crate::services::jwt_service::{Permission, SessionType};
+use crate::services::revocation_store::RevocationStore;
+use crate::services::token_service::TokenService;
+use crate::services::user_service::UserService;
+use crate::UserPrinciple;
+
+#[derive(Deserialize)]
+struct VerifyQuery {
+    /// session type the protected backend requires
+    session_type: Option<SessionType>
+}
+
+/// forward-auth for reverse proxy subrequests.
+/// the token was already checked by the auth filter, 401 never reaches this handler;
+/// 200 hands the identity to the proxy in headers, 403 when the session type does not fit
+#[get("/verify")]
+pub async fn verify(user: UserPrinciple, query: web::Query<VerifyQuery>) -> Result<HttpResponse, HttpErrorCode> {
+    let session_type = user.session_type.unwrap();
+    if query.session_type.is_some_and(|required| required != session_type) {
+        return Err(HttpErrorCode::Forbidden {message : ErrorResponse {message: "session type not allowed".to_string(), error_code : "forbidden".to_string()}});
+    }
+    let mut response = HttpResponse::Ok();
+    response.header("X-Auth-User", user.email.unwrap())
+        .header("X-Auth-Session-Type", session_type.to_string())
+        .header("X-Auth-Scopes", user.scopes.join(" "))
+        .header("Cache-Control", "no-store");
+    if let Some(aud) = user.aud {
+        response.header("X-Auth-Audience", aud);
+    }
+    Ok(response.finish())
+}
+
+/// end the current session, the bearer token is revoked by its jwt_id
+/// and the refresh token, when given and issued to the same user, together with its family
+#[post("/logout")]
+pub async fn logout(
+    user: UserPrinciple,
+    req: Option<web::Json<LogoutRequest>>,
+    revocation_store: web::Data<dyn RevocationStore>,
+    pool: web::Data<MySqlPool>) -> Result<HttpResponse, HttpErrorCode> {
+    match user.jwt_id {
+        Some(ref jwt_id) => revocation_store.revoke_token(jwt_id, user.expires_at).await,
+        // tokens without an id can only be ended through their subject
+        None => revocation_store.revoke_subject(user.email.as_ref().unwrap()).await
+    }
+    if let Some(refresh_token) = req.and_then(|req| req.into_inner().refresh_token) {
+        TokenService::new(pool.get_ref()).revoke(&refresh_token, user.email.as_ref().unwrap()).await;
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// end every session of a user, registered for holders of the GLOBAL_DELETE permission
+pub async fn revoke_user(
+    req: web::Json<RevokeUserRequest>,
+    revocation_store: web::Data<dyn RevocationStore>,
+    pool: web::Data<MySqlPool>) -> Result<HttpResponse, HttpErrorCode> {
+    let entity = UserService::new(pool.get_ref()).fetch_by_email(&req.identity).await
+        .ok_or_else(|| HttpErrorCode::BadRequest {message : ErrorResponse {message: "unknown identity".to_string(), error_code : "bad_request".to_string()}})?;
+    revocation_store.revoke_subject(entity.email.as_str()).await;
+    TokenService::new(pool.get_ref()).revoke_user(entity.id.unwrap()).await;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::scope("/auth")
+        .service(verify)
+        .service(logout)
+        .service(web::resource("/revoke")
+            .wrap(RequirePermission(Permission::GLOBAL_DELETE))
+            .route(web::post().to(revoke_user))));
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use actix_web::{App, test};
+    use actix_web::http::StatusCode;
+    use sqlx::mysql::MySqlPoolOptions;
+
+    use crate::filters::authentication_filter;
+    use crate::filters::route_policy::RoutePolicy;
+    use crate::services::jwt_service::JwtClaims;
+    use crate::services::key_manager::{test_claims, test_key_manager, test_token};
+    use crate::services::revocation_store::InMemoryRevocationStore;
+
+    use super::*;
+
+    fn token(session_type: SessionType) -> String {
+        test_token(JwtClaims { session_type: Some(session_type), ..test_claims() })
+    }
+
+    #[actix_rt::test]
+    async fn test_forward_auth() {
+        let store: Arc<dyn RevocationStore> = Arc::new(InMemoryRevocationStore::new());
+        let mut app = test::init_service(App::new()
+            .wrap(authentication_filter::AuthFilter::new(Arc::new(RoutePolicy::new())))
+            .app_data(web::Data::new(test_key_manager()))
+            .app_data(web::Data::from(store))
+            .configure(config)).await;
+
+        let req = test::TestRequest::get().uri("/auth/verify")
+            .header("Authorization", format!("bearer {}", token(SessionType::USER)))
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers().get("X-Auth-User").unwrap(), "moe@gmail.com");
+        assert_eq!(resp.headers().get("X-Auth-Session-Type").unwrap(), "USER");
+
+        let req = test::TestRequest::get().uri("/auth/verify?session_type=SYSADMIN")
+            .header("Authorization", format!("bearer {}", token(SessionType::USER)))
+            .to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::FORBIDDEN);
+
+        let req = test::TestRequest::get().uri("/auth/verify").to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_rt::test]
+    async fn test_revoke_user_needs_permission() {
+        let store: Arc<dyn RevocationStore> = Arc::new(InMemoryRevocationStore::new());
+        let pool = MySqlPoolOptions::new()
+            .connect_timeout(std::time::Duration::from_millis(100))
+            .connect_lazy("mysql://nobody@localhost/iot").unwrap();
+        let mut app = test::init_service(App::new()
+            .wrap(authentication_filter::AuthFilter::new(Arc::new(RoutePolicy::new())))
+            .app_data(web::Data::new(test_key_manager()))
+            .app_data(web::Data::from(store))
+            .data(pool)
+            .configure(config)).await;
+
+        // an operator gets the permission through user_permission, the identity is unknown to the unreachable database
+        let operator = test_token(JwtClaims { permissions: vec![Permission::GLOBAL_DELETE], ..test_claims() });
+        for (token, status) in [(token(SessionType::USER), StatusCode::FORBIDDEN), (operator, StatusCode::BAD_REQUEST)] {
+            let req = test::TestRequest::post().uri("/auth/revoke")
+                .header("Authorization", format!("bearer {}", token))
+                .set_json(&RevokeUserRequest { identity: "ahmed@gmail.com".to_string() })
+                .to_request();
+            assert_eq!(test::call_service(&mut app, req).await.status(), status);
+        }
+    }
+}
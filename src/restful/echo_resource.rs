@@ -2,15 +2,18 @@ use std::sync::{Mutex, RwLock};
 
 use actix_web::{App, Error, get, guard, HttpRequest, HttpResponse, HttpServer, post, Responder, web};
 use actix_web::body::Body;
+use actix_web::http::Method;
 use actix_web::web::Data;
 use futures::future::{ready, Ready};
 use log::debug;
 use serde::{Deserialize, Serialize};
 
 use crate::{main, UserPrinciple};
-use crate::services::jwt_service::SessionType;
+use crate::services::jwt_service::{Permission, SessionType};
 
 use crate::exceptions::error_base::{ErrorResponse, HttpErrorCode};
+use crate::filters::authorization_filter::RequirePermission;
+use crate::filters::route_policy::{RouteAccess, RoutePolicy};
 use crate::filters::authentication_filter::{ContentTypeHeader, MethodAllowed};
 use uuid::Uuid;
 use sqlx::{Pool, MySql, MySqlPool, Executor};
@@ -89,7 +92,7 @@ async fn error() -> Result<String, HttpErrorCode> {
     Err(HttpErrorCode::BadRequest { message: ErrorResponse { message: "missing user id".into(), error_code: "MissingUserId".into() } })
 }
 
-#[post("/write_user")]
+/// registered for principals holding GLOBAL_CREATE only
 pub async fn mock(user: UserPrinciple, pool: web::Data<MySqlPool>) -> impl Responder {
     // let mut conn = pool.get_conn().unwrap().unwrap();
     // let statement = conn.prep(r"INSERT INTO user(first_name, last_name, email, phone_number, language_id) VALUES(:first_name,:last_name,:email,:phone_number,:language_id)").unwrap();
@@ -102,7 +105,8 @@ pub async fn mock(user: UserPrinciple, pool: web::Data<MySqlPool>) -> impl Respo
         last_name: None,
         id: None,
         salt: None,
-        verifier: None
+        verifier: None,
+        srp_group: None
     };
     let done: Result<MySqlDone, sqlx::Error> = sqlx::query("INSERT INTO user(first_name, last_name, email, phone_number, language_id) VALUES(?,?,?,?,?)")
         .bind(&e.first_name)
@@ -114,6 +118,12 @@ pub async fn mock(user: UserPrinciple, pool: web::Data<MySqlPool>) -> impl Respo
 }
 
 
+/// the greeting needs no session, the counter greets a user when one is signed in
+pub fn policy(policy: &mut RoutePolicy) {
+    policy.method(Method::GET, "/echo/", RouteAccess::PUBLIC)
+        .method(Method::GET, "/echo/counter", RouteAccess::OPTIONAL);
+}
+
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(web::scope("/echo")
         .service(echo)
@@ -121,7 +131,9 @@ pub fn config(cfg: &mut web::ServiceConfig) {
         .service(users)
         .service(index)
         .service(error)
-        .service(mock));
+        .service(web::resource("/write_user")
+            .wrap(RequirePermission(Permission::GLOBAL_CREATE))
+            .route(web::post().to(mock))));
 }
 
 #[cfg(test)]
@@ -1,4 +1,8 @@
 pub mod echo_resource;
-pub mod facebook_resource;
+pub mod social_login_resource;
 pub mod user_resource;
 pub mod srp_resource;
+pub mod well_known_resource;
+pub mod token_resource;
+pub mod auth_resource;
+pub mod oauth_resource;
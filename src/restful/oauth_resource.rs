@@ -0,0 +1,464 @@
+use actix_web::{get, HttpRequest, HttpResponse, post, web};
+use actix_web::http::Method;
+use chrono::{Duration, Utc};
+use log::debug;
+use reqwest::Url;
+use sqlx::MySqlPool;
+
+use crate::entities::authorization_entities::{AuthorizeQuery, DeviceApprovalRequest, DeviceAuthorizationRequest, DeviceAuthorizationResponse, PendingAuthorization};
+use crate::entities::introspection_entities::{IntrospectionRequest, IntrospectionResponse};
+use crate::entities::oauth_client_entity::OAuthClientEntity;
+use crate::entities::oidc_entities::UserInfoResponse;
+use crate::entities::token_entities::{TokenRequest, TokenResponse};
+use crate::exceptions::error_base::{ErrorResponse, HttpErrorCode};
+use crate::filters::route_policy::{RouteAccess, RoutePolicy};
+use crate::filters::authentication_filter::session_claims;
+use crate::restful::social_login_resource::start_login;
+use crate::ouath::oauth::LoginState;
+use crate::ouath::provider::OAuthProviders;
+use crate::services::authorization_code_service::AuthorizationCodeService;
+use crate::services::client_service::{basic_credentials, ClientService};
+use crate::services::device_authorization_service::{DeviceAuthorizationService, DevicePollOutcome};
+use crate::services::jwt_service::SessionType;
+use crate::services::key_manager::KeyManager;
+use crate::services::oidc_service::{client_scope, DEVICE_CODE_GRANT_TYPE, invalid_scope, narrow_scope, OidcConfiguration};
+use crate::services::revocation_store::RevocationStore;
+use crate::services::token_service::{RefreshOutcome, TokenService};
+use crate::services::user_service::UserService;
+use crate::UserPrinciple;
+
+/// RFC 6749 authorization code grant for our own apps, PKCE S256 is required of every client.
+/// a user with a session, the bearer token of an srp login, is answered with a code right away,
+/// one without is sent to the social login named by provider and answered from its callback
+#[get("/authorize")]
+pub async fn authorize(
+    http_req: HttpRequest,
+    user: Option<UserPrinciple>,
+    query: web::Query<AuthorizeQuery>,
+    oidc_config: web::Data<OidcConfiguration>,
+    providers: web::Data<OAuthProviders>,
+    pool: web::Data<MySqlPool>) -> Result<HttpResponse, HttpErrorCode> {
+    let query = query.into_inner();
+    // nothing is redirected before the redirect_uri is known to belong to the client
+    let client = ClientService::new(pool.get_ref()).find(&query.client_id).await
+        .filter(|client| client.allows_redirect(&query.redirect_uri))
+        .ok_or_else(|| invalid_request("unknown client or redirect_uri"))?;
+    if query.response_type != "code" {
+        return Err(HttpErrorCode::BadRequest {message : ErrorResponse {message: "only the code response type is supported".to_string(), error_code : "unsupported_response_type".to_string()}});
+    }
+    let code_challenge = match (query.code_challenge, query.code_challenge_method.as_deref()) {
+        (Some(code_challenge), Some("S256")) => code_challenge,
+        _ => return Err(invalid_request("code_challenge with code_challenge_method S256 required"))
+    };
+    // a session only hands on what it was granted itself, and only what the client is allowed
+    let granted = match user {
+        Some(ref user) => user.scopes.join(" "),
+        None => oidc_config.scopes_supported.join(" ")
+    };
+    let scope = client_scope(&granted, &client.allowed_scopes, query.scope.as_deref()).ok_or_else(invalid_scope)?;
+    let authorization = PendingAuthorization {
+        client_id: client.client_id,
+        redirect_uri: query.redirect_uri,
+        scope,
+        state: query.state,
+        code_challenge,
+        nonce: query.nonce,
+    };
+
+    match (user, query.provider.as_deref()) {
+        (Some(user), _) => {
+            let entity = UserService::new(pool.get_ref()).fetch_by_email(user.email.as_ref().unwrap()).await
+                .ok_or_else(|| invalid_request("unknown user"))?;
+            let auth_time = Utc::now().timestamp();
+            authorization_redirect(pool.get_ref(), oidc_config.get_ref(), &authorization, entity.id.unwrap(), auth_time).await
+        }
+        (None, Some(provider_name)) => {
+            let provider = providers.get(provider_name).ok_or_else(|| invalid_request("unknown provider"))?;
+            let login_state = LoginState { nonce: authorization.nonce.clone(), scope: authorization.scope.clone(), authorization: Some(authorization), link_user_id: None };
+            let (url, cookie) = start_login(&http_req, pool.get_ref(), oidc_config.get_ref(), provider_name, provider, login_state).await?;
+            Ok(HttpResponse::Found()
+                .header("Location", url)
+                .cookie(cookie)
+                .finish())
+        }
+        (None, _) => {
+            Err(HttpErrorCode::UnAuthorized {message : ErrorResponse {message: "log in with srp and send the bearer token, or name a provider".to_string(), error_code : "login_required".to_string()}})
+        }
+    }
+}
+
+/// issue a code for the user and send it to the client's redirect_uri together with its state
+pub async fn authorization_redirect(
+    pool: &MySqlPool,
+    oidc_config: &OidcConfiguration,
+    authorization: &PendingAuthorization,
+    user_id: u32,
+    auth_time: i64) -> Result<HttpResponse, HttpErrorCode> {
+    let ttl = Duration::seconds(oidc_config.authorization_code_ttl_seconds);
+    let code = AuthorizationCodeService::new(pool).issue(authorization, user_id, auth_time, ttl).await
+        .ok_or_else(|| HttpErrorCode::ServiceUnavailable {message : ErrorResponse {message: "authorization code not issued".to_string(), error_code : "temporarily_unavailable".to_string()}})?;
+    let mut location = Url::parse(&authorization.redirect_uri)
+        .map_err(|_| invalid_request("redirect_uri is not a url"))?;
+    location.query_pairs_mut().append_pair("code", &code);
+    if let Some(ref state) = authorization.state {
+        location.query_pairs_mut().append_pair("state", state);
+    }
+    Ok(HttpResponse::Found()
+        .header("Location", location.as_str())
+        .finish())
+}
+
+/// RFC 6749 token endpoint exchanging authorization codes and refresh tokens for our tokens and
+/// issuing service tokens to confidential clients in their own name.
+/// confidential clients authenticate with basic credentials, public ones name themselves
+/// with client_id and rely on the PKCE code_verifier alone
+#[post("/token")]
+pub async fn token(
+    http_req: HttpRequest,
+    req: web::Form<TokenRequest>,
+    oidc_config: web::Data<OidcConfiguration>,
+    keys: web::Data<KeyManager>,
+    revocation_store: web::Data<dyn RevocationStore>,
+    pool: web::Data<MySqlPool>) -> Result<HttpResponse, HttpErrorCode> {
+    let req = req.into_inner();
+    let client = token_client(&http_req, req.client_id.as_ref(), pool.get_ref()).await.ok_or_else(invalid_client)?;
+    let token_response = match req.grant_type.as_str() {
+        "authorization_code" => authorization_code_grant(&client, req, oidc_config.get_ref(), keys.get_ref(), pool.get_ref()).await?,
+        "client_credentials" => client_credentials_grant(&client, req, oidc_config.get_ref(), keys.get_ref())?,
+        DEVICE_CODE_GRANT_TYPE => device_code_grant(&client, req, oidc_config.get_ref(), keys.get_ref(), pool.get_ref()).await?,
+        "refresh_token" => refresh_token_grant(&client, req, oidc_config.get_ref(), keys.get_ref(), &**revocation_store, pool.get_ref()).await?,
+        _ => return Err(HttpErrorCode::BadRequest {message : ErrorResponse {message: "grant type not supported".to_string(), error_code : "unsupported_grant_type".to_string()}})
+    };
+    Ok(HttpResponse::Ok()
+        .header("Cache-Control", "no-store")
+        .json(token_response))
+}
+
+async fn authorization_code_grant(
+    client: &OAuthClientEntity,
+    req: TokenRequest,
+    oidc_config: &OidcConfiguration,
+    keys: &KeyManager,
+    pool: &MySqlPool) -> Result<TokenResponse, HttpErrorCode> {
+    let (code, redirect_uri, code_verifier) = match (req.code, req.redirect_uri, req.code_verifier) {
+        (Some(code), Some(redirect_uri), Some(code_verifier)) => (code, redirect_uri, code_verifier),
+        _ => return Err(invalid_request("code, redirect_uri and code_verifier required"))
+    };
+    let grant = AuthorizationCodeService::new(pool).redeem(&code, &client.client_id, &redirect_uri, &code_verifier).await
+        .ok_or_else(|| HttpErrorCode::BadRequest {message : ErrorResponse {message: "invalid authorization code".to_string(), error_code : "invalid_grant".to_string()}})?;
+
+    let email = grant.email.unwrap();
+    let permissions = UserService::new(pool).fetch_permissions(grant.user_id).await;
+    let ttl = Duration::seconds(oidc_config.refresh_token_ttl_seconds);
+    let refresh_token = TokenService::new(pool).issue_refresh_token(grant.user_id, SessionType::USER, &grant.scope, Some(&client.client_id), ttl).await;
+    // an id_token is only part of an openid request, its audience is the client
+    let id_token = match grant.scope.split_whitespace().any(|scope| scope == "openid") {
+        true => Some(oidc_config.id_token(keys, &client.client_id, &email, grant.nonce, grant.auth_time as usize)),
+        false => None
+    };
+    Ok(TokenResponse {
+        access_token: oidc_config.access_token(keys, &email, SessionType::USER, None, permissions, grant.scope.clone()),
+        token_type: "bearer".to_string(),
+        expires_in: oidc_config.access_token_ttl_seconds,
+        refresh_token,
+        id_token,
+        scope: Some(grant.scope),
+    })
+}
+
+/// RFC 6749 4.4, a service session of the client itself. there is no user to refresh
+/// a login for, so no refresh token, the client asks for a new token when this one expires
+fn client_credentials_grant(
+    client: &OAuthClientEntity,
+    req: TokenRequest,
+    oidc_config: &OidcConfiguration,
+    keys: &KeyManager) -> Result<TokenResponse, HttpErrorCode> {
+    if client.is_public() {
+        return Err(HttpErrorCode::BadRequest {message : ErrorResponse {message: "public clients cannot act in their own name".to_string(), error_code : "unauthorized_client".to_string()}});
+    }
+    let scope = narrow_scope(&client.allowed_scopes, req.scope.as_deref()).ok_or_else(invalid_scope)?;
+    Ok(TokenResponse {
+        access_token: oidc_config.access_token(keys, &client.client_id, SessionType::SERVICE, None, vec![], scope.clone()),
+        token_type: "bearer".to_string(),
+        expires_in: oidc_config.access_token_ttl_seconds,
+        refresh_token: None,
+        id_token: None,
+        scope: Some(scope),
+    })
+}
+
+/// RFC 8628 3.4, the device polls until its user answered. the error codes tell it to keep polling,
+/// to poll slower or to give up. tokens are issued once, like for an authorization code
+async fn device_code_grant(
+    client: &OAuthClientEntity,
+    req: TokenRequest,
+    oidc_config: &OidcConfiguration,
+    keys: &KeyManager,
+    pool: &MySqlPool) -> Result<TokenResponse, HttpErrorCode> {
+    let device_code = req.device_code.ok_or_else(|| invalid_request("device_code required"))?;
+    let device_error = |error_code: &str, message: &str| HttpErrorCode::BadRequest {message : ErrorResponse {message: message.to_string(), error_code : error_code.to_string()}};
+    match DeviceAuthorizationService::new(pool).poll(&device_code, &client.client_id).await {
+        DevicePollOutcome::Approved { user_id, email, scope, auth_time } => {
+            let permissions = UserService::new(pool).fetch_permissions(user_id).await;
+            let ttl = Duration::seconds(oidc_config.refresh_token_ttl_seconds);
+            let refresh_token = TokenService::new(pool).issue_refresh_token(user_id, SessionType::USER, &scope, Some(&client.client_id), ttl).await;
+            let id_token = match scope.split_whitespace().any(|scope| scope == "openid") {
+                true => Some(oidc_config.id_token(keys, &client.client_id, &email, None, auth_time as usize)),
+                false => None
+            };
+            Ok(TokenResponse {
+                access_token: oidc_config.access_token(keys, &email, SessionType::USER, None, permissions, scope.clone()),
+                token_type: "bearer".to_string(),
+                expires_in: oidc_config.access_token_ttl_seconds,
+                refresh_token,
+                id_token,
+                scope: Some(scope),
+            })
+        }
+        DevicePollOutcome::Pending => Err(device_error("authorization_pending", "the user has not answered yet")),
+        DevicePollOutcome::SlowDown => Err(device_error("slow_down", "polling too fast")),
+        DevicePollOutcome::Denied => Err(device_error("access_denied", "the user denied the device")),
+        DevicePollOutcome::Expired => Err(device_error("expired_token", "the device code expired")),
+        DevicePollOutcome::Invalid => Err(device_error("invalid_grant", "invalid device code")),
+    }
+}
+
+/// RFC 6749 6, the client swaps a refresh token it was issued for new tokens.
+/// rotation works like /token/refresh, a token issued to another client is invalid here
+async fn refresh_token_grant(
+    client: &OAuthClientEntity,
+    req: TokenRequest,
+    oidc_config: &OidcConfiguration,
+    keys: &KeyManager,
+    revocation_store: &dyn RevocationStore,
+    pool: &MySqlPool) -> Result<TokenResponse, HttpErrorCode> {
+    let refresh_token = req.refresh_token.ok_or_else(|| invalid_request("refresh_token required"))?;
+    let ttl = Duration::seconds(oidc_config.refresh_token_ttl_seconds);
+    match TokenService::new(pool).rotate(&refresh_token, Some(&client.client_id), req.scope.as_deref(), ttl, revocation_store).await {
+        RefreshOutcome::Rotated { user_id, email, session_type, scope, refresh_token } => {
+            let permissions = UserService::new(pool).fetch_permissions(user_id).await;
+            Ok(TokenResponse {
+                access_token: oidc_config.access_token(keys, &email, session_type, None, permissions, scope.clone()),
+                token_type: "bearer".to_string(),
+                expires_in: oidc_config.access_token_ttl_seconds,
+                refresh_token: Some(refresh_token),
+                id_token: None,
+                scope: Some(scope),
+            })
+        }
+        RefreshOutcome::InvalidScope => Err(invalid_scope()),
+        RefreshOutcome::Reused | RefreshOutcome::Invalid => {
+            Err(HttpErrorCode::BadRequest {message : ErrorResponse {message: "invalid refresh token".to_string(), error_code : "invalid_grant".to_string()}})
+        }
+    }
+}
+
+/// RFC 8628 3.1, a device without a browser asks for a user code to show its user
+#[post("/device_authorization")]
+pub async fn device_authorization(
+    http_req: HttpRequest,
+    req: web::Form<DeviceAuthorizationRequest>,
+    oidc_config: web::Data<OidcConfiguration>,
+    pool: web::Data<MySqlPool>) -> Result<HttpResponse, HttpErrorCode> {
+    let req = req.into_inner();
+    let client = token_client(&http_req, req.client_id.as_ref(), pool.get_ref()).await.ok_or_else(invalid_client)?;
+    // the approving session narrows it further
+    let scope = client_scope(&oidc_config.scopes_supported.join(" "), &client.allowed_scopes, req.scope.as_deref()).ok_or_else(invalid_scope)?;
+    let ttl = Duration::seconds(oidc_config.device_code_ttl_seconds);
+    let (device_code, user_code) = DeviceAuthorizationService::new(pool.get_ref())
+        .start(&client.client_id, &scope, ttl, oidc_config.device_poll_interval_seconds).await
+        .ok_or_else(|| HttpErrorCode::ServiceUnavailable {message : ErrorResponse {message: "device code not issued".to_string(), error_code : "temporarily_unavailable".to_string()}})?;
+    // the page of our app, users sign in there before the code is answered
+    let verification_uri = oidc_config.device_verification_uri.clone();
+    let response = DeviceAuthorizationResponse {
+        device_code,
+        verification_uri_complete: format!("{}?user_code={}", verification_uri, user_code),
+        user_code,
+        verification_uri,
+        expires_in: oidc_config.device_code_ttl_seconds,
+        interval: oidc_config.device_poll_interval_seconds,
+    };
+    Ok(HttpResponse::Ok()
+        .header("Cache-Control", "no-store")
+        .json(response))
+}
+
+/// the signed in user approves or denies the device showing the user code.
+/// the device gets no more than the session approving it was granted
+#[post("/device")]
+pub async fn approve_device(
+    user: UserPrinciple,
+    req: web::Json<DeviceApprovalRequest>,
+    pool: web::Data<MySqlPool>) -> Result<HttpResponse, HttpErrorCode> {
+    let forbidden = |message: &str| HttpErrorCode::Forbidden {message : ErrorResponse {message: message.to_string(), error_code : "forbidden".to_string()}};
+    let entity = UserService::new(pool.get_ref()).fetch_by_email(user.email.as_ref().unwrap()).await
+        .ok_or_else(|| forbidden("only users can approve devices"))?;
+    let mut device_service = DeviceAuthorizationService::new(pool.get_ref());
+    let grant = device_service.find_pending(&req.user_code).await
+        .ok_or_else(|| invalid_request("unknown or expired user code"))?;
+    if req.approve && narrow_scope(&user.scopes.join(" "), Some(&grant.scope)).is_none() {
+        return Err(forbidden("the session was not granted the scope of the device"));
+    }
+    if !device_service.answer(&grant, entity.id.unwrap(), req.approve).await {
+        return Err(invalid_request("unknown or expired user code"));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// OIDC userinfo, the user of an openid session with the claims its scopes reach
+#[get("/userinfo")]
+pub async fn userinfo(
+    user: UserPrinciple,
+    pool: web::Data<MySqlPool>) -> Result<HttpResponse, HttpErrorCode> {
+    let forbidden = |message: &str, error_code: &str| HttpErrorCode::Forbidden {message : ErrorResponse {message: message.to_string(), error_code : error_code.to_string()}};
+    let granted = |name: &str| user.scopes.iter().any(|scope| scope == name);
+    if !granted("openid") {
+        return Err(forbidden("the session was not granted openid", "insufficient_scope"));
+    }
+    let entity = UserService::new(pool.get_ref()).fetch_by_email(user.email.as_ref().unwrap()).await
+        .ok_or_else(|| forbidden("only users have user info", "forbidden"))?;
+    let (given_name, family_name) = match granted("profile") {
+        true => (entity.first_name, entity.last_name),
+        false => (None, None)
+    };
+    let response = UserInfoResponse {
+        email: Some(entity.email.clone()).filter(|_| granted("email")),
+        sub: entity.email,
+        given_name,
+        family_name,
+    };
+    Ok(HttpResponse::Ok()
+        .header("Cache-Control", "no-store")
+        .json(response))
+}
+
+/// the client calling the token endpoint, confidential clients must not skip their secret
+async fn token_client(http_req: &HttpRequest, client_id: Option<&String>, pool: &MySqlPool) -> Option<OAuthClientEntity> {
+    let mut client_service = ClientService::new(pool);
+    match basic_credentials(http_req.headers()) {
+        Some((client_id, client_secret)) => client_service.authenticate(&client_id, &client_secret).await,
+        None => client_service.find(client_id?).await.filter(|client| client.is_public())
+    }
+}
+
+/// RFC 7662 token introspection for services that cannot verify our tokens themselves.
+/// callers authenticate as a registered client with HTTP basic credentials
+#[post("/introspect")]
+pub async fn introspect(
+    http_req: HttpRequest,
+    req: web::Form<IntrospectionRequest>,
+    keys: web::Data<KeyManager>,
+    revocation_store: web::Data<dyn RevocationStore>,
+    pool: web::Data<MySqlPool>) -> Result<HttpResponse, HttpErrorCode> {
+    let (client_id, client_secret) = basic_credentials(http_req.headers())
+        .ok_or_else(invalid_client)?;
+    let client = ClientService::new(pool.get_ref()).authenticate(&client_id, &client_secret).await
+        .ok_or_else(invalid_client)?;
+    debug!("token introspection by {} ({})", client.name, client.client_id);
+
+    let response = match session_claims(req.token.as_str(), keys.get_ref()) {
+        Some(claims) if !revocation_store.is_revoked(&claims).await => {
+            IntrospectionResponse {
+                active: true,
+                sub: claims.sub,
+                exp: Some(claims.exp),
+                iat: Some(claims.iat),
+                scope: claims.scope,
+                session_type: claims.session_type,
+                jti: claims.jwt_id,
+            }
+        }
+        // unknown, expired and revoked tokens look the same
+        _ => IntrospectionResponse::default()
+    };
+    Ok(HttpResponse::Ok()
+        .header("Cache-Control", "no-store")
+        .json(response))
+}
+
+fn invalid_client() -> HttpErrorCode {
+    HttpErrorCode::UnAuthorized {message : ErrorResponse {message: "invalid client".to_string(), error_code : "invalid_client".to_string()}}
+}
+
+fn invalid_request(message: &str) -> HttpErrorCode {
+    HttpErrorCode::BadRequest {message : ErrorResponse {message: message.to_string(), error_code : "invalid_request".to_string()}}
+}
+
+/// callers of introspect, token and device_authorization authenticate as clients inside the handlers,
+/// authorize answers users with a session directly and sends the others to log in.
+/// approving a device and userinfo need a session
+pub fn policy(policy: &mut RoutePolicy) {
+    policy.exact("/oauth/introspect", RouteAccess::PUBLIC)
+        .exact("/oauth/token", RouteAccess::PUBLIC)
+        .exact("/oauth/device_authorization", RouteAccess::PUBLIC)
+        .method(Method::GET, "/oauth/authorize", RouteAccess::OPTIONAL);
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::scope("/oauth")
+        .service(authorize)
+        .service(token)
+        .service(device_authorization)
+        .service(approve_device)
+        .service(userinfo)
+        .service(introspect));
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use actix_web::{App, test};
+    use actix_web::http::StatusCode;
+
+    use crate::entities::introspection_entities::IntrospectionResponse;
+    use crate::services::key_manager::test_key_manager;
+    use crate::services::oidc_service::test_oidc_configuration;
+    use crate::services::revocation_store::InMemoryRevocationStore;
+
+    use super::*;
+
+    #[actix_rt::test]
+    async fn test_token_requires_client() {
+        // neither basic credentials nor a client_id, the database is never asked
+        let pool = MySqlPool::connect_lazy("mysql://nobody@localhost/iot").unwrap();
+        let store: Arc<dyn RevocationStore> = Arc::new(InMemoryRevocationStore::new());
+        let mut app = test::init_service(App::new()
+            .data(pool)
+            .app_data(web::Data::new(test_oidc_configuration()))
+            .app_data(web::Data::new(test_key_manager()))
+            .app_data(web::Data::from(store))
+            .configure(config)).await;
+        let req = test::TestRequest::post().uri("/oauth/token")
+            .set_form(&TokenRequest {
+                grant_type: "authorization_code".to_string(),
+                code: Some("code".to_string()),
+                redirect_uri: Some("https://app.infotamia.com/callback".to_string()),
+                client_id: None,
+                code_verifier: Some("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk".to_string()),
+                scope: None,
+                device_code: None,
+                refresh_token: None,
+            })
+            .to_request();
+        let res = test::call_service(&mut app, req).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_rt::test]
+    async fn test_userinfo_needs_a_session() {
+        let pool = MySqlPool::connect_lazy("mysql://nobody@localhost/iot").unwrap();
+        let mut app = test::init_service(App::new()
+            .data(pool)
+            .configure(config)).await;
+        let req = test::TestRequest::get().uri("/oauth/userinfo").to_request();
+        let res = test::call_service(&mut app, req).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_inactive_response_only_reports_active() {
+        let body = serde_json::to_string(&IntrospectionResponse::default()).unwrap();
+        assert_eq!(body, r#"{"active":false}"#);
+    }
+}
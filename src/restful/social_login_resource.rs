@@ -0,0 +1,245 @@
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, Responder, get, post, web, Error};
+use actix_web::cookie::{Cookie, SameSite};
+use crate::ouath::apple::first_login_names;
+use crate::ouath::oauth::LoginState;
+use crate::ouath::provider::{OAuthProvider, OAuthProviders};
+use crate::exceptions::error_base::{HttpErrorCode, ErrorResponse};
+use crate::services::jwt_service::SessionType;
+use crate::services::authorization_code_service::pkce_challenge;
+use crate::services::external_identity_service::{ExternalIdentityService, IdentityOutcome};
+use crate::services::login_state_service::LoginStateService;
+use crate::services::token_service::generate_token;
+use chrono::{Utc, Duration};
+use serde::Deserialize;
+use std::collections::HashMap;
+use crate::services::user_service::UserService;
+use sqlx::{MySql, MySqlPool, Pool};
+use log::info;
+use crate::services::key_manager::KeyManager;
+use crate::filters::route_policy::{RouteAccess, RoutePolicy};
+use crate::services::oidc_service::{invalid_scope, OidcConfiguration};
+use crate::entities::token_entities::TokenResponse;
+use crate::services::token_service::TokenService;
+use crate::restful::oauth_resource::authorization_redirect;
+
+/// ties the login states to the browser that started them, a callback opened elsewhere is refused
+const LOGIN_BINDING_COOKIE: &str = "login_binding";
+
+/// what the provider sends back, in the query or as a form_post
+#[derive(Deserialize)]
+struct Callback {
+    code: String,
+    state: String,
+    /// apple only, json with the name of the user on the first login
+    user: Option<String>
+}
+
+#[derive(Deserialize)]
+struct LoginQuery {
+    nonce: Option<String>,
+    /// space delimited scopes for the access token, all supported ones when missing
+    scope: Option<String>
+}
+
+/// the configured provider named by the path, a built-in one like google or one of oauth_providers.json
+pub fn provider<'a>(providers: &'a OAuthProviders, name: &str) -> Result<&'a OAuthProvider, HttpErrorCode> {
+    providers.get(name)
+        .ok_or_else(|| HttpErrorCode::BadRequest {message : ErrorResponse {message: format!("unknown provider {}", name), error_code : "unknown_provider".to_string()}})
+}
+
+/// store the login and build the provider url for it. the browser keeps its binding cookie
+/// across logins, the one returned is to be set on the response
+pub async fn start_login(
+    http_req: &HttpRequest,
+    pool: &MySqlPool,
+    oidc_config: &OidcConfiguration,
+    provider_name: &str,
+    provider: &OAuthProvider,
+    login_state: LoginState) -> Result<(String, Cookie<'static>), HttpErrorCode> {
+    let binding = http_req.cookie(LOGIN_BINDING_COOKIE)
+        .map(|cookie| cookie.value().to_string())
+        .unwrap_or_else(generate_token);
+    let ttl = Duration::seconds(oidc_config.login_state_ttl_seconds);
+    let started = LoginStateService::new(pool).start(provider_name, &binding, login_state, ttl).await
+        .ok_or_else(|| HttpErrorCode::ServiceUnavailable {message : ErrorResponse {message: "login could not be started".to_string(), error_code : "temporarily_unavailable".to_string()}})?;
+    let url = provider.get_authorization_url(&started.state, &pkce_challenge(&started.code_verifier));
+    // SameSite=None, apple's form_post callback is a cross site POST.
+    // Path=/, logins also start from the link and authorize routes and must find the same cookie
+    let cookie = Cookie::build(LOGIN_BINDING_COOKIE, binding)
+        .path("/")
+        .http_only(true)
+        .secure(true)
+        .same_site(SameSite::None)
+        .finish();
+    Ok((url, cookie))
+}
+
+/// step one login
+/// return a url String.
+#[get("/{provider}/login1")]
+pub async fn login_step_1(
+    http_req: HttpRequest,
+    path: web::Path<String>,
+    providers: web::Data<OAuthProviders>,
+    oidc_config: web::Data<OidcConfiguration>,
+    pool: web::Data<Pool<MySql>>,
+    query: web::Query<LoginQuery>) -> Result<HttpResponse, HttpErrorCode> {
+    let auth_service = provider(providers.get_ref(), &path)?;
+    let query = query.into_inner();
+    let scope = oidc_config.grant_scope(query.scope.as_deref()).ok_or_else(invalid_scope)?;
+    let login_state = LoginState { nonce: query.nonce, scope, authorization: None, link_user_id: None };
+    let (url, cookie) = start_login(&http_req, pool.get_ref(), oidc_config.get_ref(), &path, auth_service, login_state).await?;
+    Ok(HttpResponse::Ok().cookie(cookie).body(url))
+}
+
+/// the provider sends its user back here with a code and the state of step one
+#[get("/{provider}/callback")]
+pub async fn login_step_2(
+    http_req: HttpRequest,
+    path: web::Path<String>,
+    providers: web::Data<OAuthProviders>,
+    query: web::Query<Callback>,
+    keys: web::Data<KeyManager>,
+    oidc_config: web::Data<OidcConfiguration>,
+    pool: web::Data<Pool<MySql>>) -> Result<HttpResponse, HttpErrorCode> {
+    complete_login(&http_req, &path, providers.get_ref(), query.into_inner(), keys.get_ref(), oidc_config.get_ref(), pool.get_ref()).await
+}
+
+/// response_mode=form_post callback, apple posts here and adds the user's name on the first login
+#[post("/{provider}/callback")]
+pub async fn login_step_2_form_post(
+    http_req: HttpRequest,
+    path: web::Path<String>,
+    providers: web::Data<OAuthProviders>,
+    form: web::Form<Callback>,
+    keys: web::Data<KeyManager>,
+    oidc_config: web::Data<OidcConfiguration>,
+    pool: web::Data<Pool<MySql>>) -> Result<HttpResponse, HttpErrorCode> {
+    complete_login(&http_req, &path, providers.get_ref(), form.into_inner(), keys.get_ref(), oidc_config.get_ref(), pool.get_ref()).await
+}
+
+async fn complete_login(
+    http_req: &HttpRequest,
+    provider_name: &str,
+    providers: &OAuthProviders,
+    callback: Callback,
+    keys: &KeyManager,
+    oidc_config: &OidcConfiguration,
+    pool: &MySqlPool) -> Result<HttpResponse, HttpErrorCode> {
+    let auth_service = provider(providers, provider_name)?;
+    // a state is good once, for the provider and the browser it was started for
+    let binding = http_req.cookie(LOGIN_BINDING_COOKIE).map(|cookie| cookie.value().to_string());
+    let state = LoginStateService::new(pool).consume(&callback.state, provider_name, binding.as_ref()).await
+        .ok_or_else(|| HttpErrorCode::UnAuthorized {message : ErrorResponse {message: "unknown, used or foreign login state".to_string(), error_code : "unauthorized".to_string()}})?;
+    // granted at login1, checked again in case the supported scopes changed since
+    let scope = oidc_config.grant_scope(Some(&state.scope)).ok_or_else(invalid_scope)?;
+    let access_token = auth_service.get_access_token(&callback.code, &state.code_verifier).await
+        .ok_or_else(|| HttpErrorCode::UnAuthorized {message : ErrorResponse {message: "code not accepted by the provider".to_string(), error_code : "unauthorized".to_string()}})?;
+    let user_profile_optional = auth_service.get_account_details(&access_token).await;
+    match user_profile_optional {
+        None => {
+            Err(HttpErrorCode::UnAuthorized {message : ErrorResponse {message: "no user found".to_string(), error_code : "unauthorized".to_string()}})
+        }
+        Some(mut user) => {
+            if let Some(ref first_login_user) = callback.user {
+                first_login_names(&mut user, first_login_user);
+            }
+            if user.private_email {
+                info!("{} login of {} through a private relay address", provider_name, user.email);
+            }
+            let mut identity_service = ExternalIdentityService::new(pool);
+            // the login was started by a signed in user to link the provider account
+            if let Some(user_id) = state.link_user_id {
+                return match identity_service.link(user_id, provider_name, &user).await {
+                    IdentityOutcome::Linked => Ok(HttpResponse::NoContent().finish()),
+                    outcome => Err(identity_error(outcome, provider_name))
+                };
+            }
+            // the tokens are of our user, whose email may differ from the provider's
+            let entity = match identity_service.sign_in(provider_name, &user).await {
+                IdentityOutcome::SignedIn(entity) => entity,
+                outcome => return Err(identity_error(outcome, provider_name))
+            };
+            let user_id = entity.id.unwrap();
+            let auth_time = Utc::now().timestamp() as usize;
+            // the login answers an authorization request, the client gets a code instead of tokens
+            if let Some(authorization) = state.pending_authorization {
+                return authorization_redirect(pool, oidc_config, &authorization, user_id, auth_time as i64).await;
+            }
+            let ttl = Duration::seconds(oidc_config.refresh_token_ttl_seconds);
+            let permissions = UserService::new(pool).fetch_permissions(user_id).await;
+            let refresh_token = TokenService::new(pool).issue_refresh_token(user_id, SessionType::USER, &scope, None, ttl).await;
+            let jwt = oidc_config.access_token(keys, &entity.email, SessionType::USER, user.access_token.clone(), permissions, scope.clone());
+            let token_response = TokenResponse {
+                access_token: jwt.clone(),
+                token_type: "bearer".to_string(),
+                expires_in: oidc_config.access_token_ttl_seconds,
+                refresh_token,
+                id_token: Some(oidc_config.id_token(keys, &oidc_config.audience, &entity.email, state.nonce, auth_time)),
+                scope: Some(scope),
+            };
+            let response = HttpResponse::Ok().header("Authorization", format!("bearer {}", jwt)).json(token_response);
+            Ok(response)
+        }
+    }
+}
+
+fn identity_error(outcome: IdentityOutcome, provider_name: &str) -> HttpErrorCode {
+    match outcome {
+        IdentityOutcome::EmailTaken => {
+            let message = format!("a user with the email of the account exists, sign in and link it at /user/identities/{}/link", provider_name);
+            HttpErrorCode::Conflict {message : ErrorResponse {message, error_code : "account_exists".to_string()}}
+        }
+        IdentityOutcome::UnverifiedEmail => {
+            HttpErrorCode::Forbidden {message : ErrorResponse {message: "the provider has not verified the email of the account".to_string(), error_code : "unverified_email".to_string()}}
+        }
+        IdentityOutcome::Conflict => {
+            HttpErrorCode::Conflict {message : ErrorResponse {message: "the provider account is linked to another user, or the user to another account of the provider".to_string(), error_code : "identity_conflict".to_string()}}
+        }
+        _ => {
+            HttpErrorCode::ServiceUnavailable {message : ErrorResponse {message: "login could not be completed".to_string(), error_code : "temporarily_unavailable".to_string()}}
+        }
+    }
+}
+
+/// the oauth redirect flow happens before there is a session
+pub fn policy(policy: &mut RoutePolicy) {
+    policy.prefix("/iot/auth2/", RouteAccess::PUBLIC);
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::scope("/iot/auth2")
+        .service(login_step_1)
+        .service(login_step_2)
+        .service(login_step_2_form_post));
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use actix_web::{App, test};
+    use actix_web::http::StatusCode;
+    use sqlx::MySqlPool;
+
+    use crate::services::key_manager::test_key_manager;
+    use crate::services::oidc_service::test_oidc_configuration;
+
+    use super::*;
+
+    #[actix_rt::test]
+    async fn test_unknown_provider() {
+        let keys = Arc::new(test_key_manager());
+        // the provider is looked up before a login state is stored
+        let pool = MySqlPool::connect_lazy("mysql://nobody@localhost/iot").unwrap();
+        let mut app = test::init_service(App::new()
+            .data(pool)
+            .app_data(web::Data::new(OAuthProviders::from_configurations(vec![]).unwrap()))
+            .app_data(web::Data::from(keys))
+            .app_data(web::Data::new(test_oidc_configuration()))
+            .configure(config)).await;
+        let req = test::TestRequest::get().uri("/iot/auth2/github/login1").to_request();
+        let res = test::call_service(&mut app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+}
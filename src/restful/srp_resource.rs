@@ -1,24 +1,28 @@
-use actix_web::{HttpResponse, Responder, post, web, Error};
+use actix_web::{HttpResponse, Responder, get, post, web, Error};
 use crate::ouath::oauth::{FacebookAuthenticationService, BaseOAuth20Service, ExternalAccount};
 use crate::exceptions::error_base::{HttpErrorCode, ErrorResponse};
-use crate::services::jwt_service::{JwtClaims, SessionType};
+use crate::services::jwt_service::SessionType;
 use crate::services::jwt_service;
 use chrono::{Utc, Duration};
-use std::ops::Add;
-use uuid::Uuid;
 use serde::{Deserialize, Serialize};
 use serde_json;
 use std::collections::HashMap;
 use crate::services::user_service::UserService;
 use sqlx::{MySql, Pool, MySqlPool};
 use crate::entities::user_entity::UserEntity;
-use crate::entities::srp::srp_entities::{SrpStep1Request, SrpStep2Request, SrpStep2Response, SrpStep1Response};
+use crate::entities::srp::srp_entities::{SrpStep1Request, SrpStep2Request, SrpStep2Response, SrpStep1Response, SrpRegisterRequest, SrpGroupResponse};
+use crate::entities::srp::srp_group::SrpGroup;
 use std::borrow::Borrow;
-use rust_srp::{SrpServer, SrpConfig};
-use std::sync::{Mutex, PoisonError, MutexGuard};
-use std::collections::hash_map::RandomState;
-use rust_srp::bigint_helper::{convert_to_bigint, generate_random_256bit_bigint};
+use rust_srp::bigint_helper::convert_to_bigint;
+use crate::services::srp_service;
+use crate::services::srp_service::{SrpHandshake, SrpConfiguration};
+use crate::services::srp_session_store::SrpSessionStore;
+use crate::services::key_manager::KeyManager;
+use crate::filters::route_policy::{RouteAccess, RoutePolicy};
+use crate::services::oidc_service::{invalid_scope, OidcConfiguration};
+use crate::services::token_service::TokenService;
 use num_bigint::BigUint;
+use num_traits::Zero;
 
 
 #[derive(Deserialize)]
@@ -28,107 +32,217 @@ struct CallbackQuery {
 }
 /// step one login
 /// receive public key A and identity
-/// returns salt, B
+/// returns handshake id, salt, B and the srp group of the identity
 #[post("/1")]
 pub async fn login_step_1(
     srp_req: web::Json<SrpStep1Request>,
-    srp_session_map: web::Data<Mutex<HashMap<String, SrpServer>>>,
+    srp_session_store: web::Data<dyn SrpSessionStore>,
+    srp_config: web::Data<SrpConfiguration>,
     pool: web::Data<Pool<MySql>>) -> Result<HttpResponse, HttpErrorCode> {
     let req = srp_req.0.borrow();
     let identity = req.identity.clone();
-    let public_a_str = req.public_a_str.clone();
-    let n = BigUint::parse_bytes(b"B97F8C656C3DF7179C2B805BBCB3A0DC4B0B6926BF66D0A3C63CF6015625CAF9A4DB4BBE7EB34253FAB0E475A6ACFAE49FD5F22C47A71B5532911B69FE7DF4F8ACEE2F7785D75866CF6D213286FC7EBBBE3BE411ECFA10A70F0C8463DC1182C6F9B6F7666C8691B3D1AB6FD78E9CBF8AAE719EA75CA02BE87AE445C698BF0413", 16).unwrap();
-    let g = BigUint::parse_bytes(b"2", 10).unwrap();
-    let public_a = rust_srp::bigint_helper::convert_to_bigint(public_a_str.as_bytes(), 10);
+    let public_a = match BigUint::parse_bytes(req.public_a_str.as_bytes(), 10) {
+        None => {
+            return Err(HttpErrorCode::BadRequest {message : ErrorResponse {message: "invalid public value".to_string(), error_code : "bad_request".to_string()}});
+        }
+        Some(public_a) => public_a
+    };
 
     let pool_ref = pool.get_ref();
-    //let result = &mut pool.acquire().await.unwrap();
     let mut user_service = UserService::new(pool_ref);
     let option = user_service.fetch_by_email(&identity).await;
-    let mut srp_server = SrpServer::new(public_a.unwrap(), n, g);
-    match option {
-        None => {
-            Err(HttpErrorCode::UnAuthorized {message : ErrorResponse {message: "unknown".to_string(), error_code : "unauthorized".to_string()}})
+    let (salt, verifier, group) = srp_credentials(option, &identity, srp_config.get_ref());
+    let handshake = SrpHandshake::start(identity, public_a, salt.clone(), verifier, group.n(), group.g())
+        .map_err(|err| HttpErrorCode::UnAuthorized {message : ErrorResponse {message: err.to_string(), error_code : "unauthorized".to_string()}})?;
+    let public_b = handshake.public_b();
+    match srp_session_store.put(handshake).await {
+        Some(handshake_id) => {
+            let srp_step1_response = SrpStep1Response {
+                handshake_id,
+                salt_str: salt.to_string(),
+                public_b_str: public_b.to_string(),
+                group: SrpGroupResponse::new(group)
+            };
+
+            let body = serde_json::to_string(&srp_step1_response).unwrap();
+            Ok(HttpResponse::Ok()
+                .content_type("application/json")
+                .body(body))
         }
-        Some(user) => {
-            let salt_str = user.salt.clone().unwrap();
-            let salt = rust_srp::bigint_helper::convert_to_bigint(salt_str.as_bytes(), 10).unwrap();
-            let verifier_str = user.verifier.clone().unwrap();
-            let verifier = rust_srp::bigint_helper::convert_to_bigint(verifier_str.as_bytes(), 10).unwrap();
-            let srp_1_result = srp_server.step_1(identity.clone(), salt, verifier);
-            match srp_1_result {
-                Ok(public_b) => {
-                    match srp_session_map.lock() {
-                        Ok(mut sessions) => {
-                            sessions.insert(user.email.clone(), srp_server);
-                            let srp_step1_response = SrpStep1Response {
-                                salt_str,
-                                public_b_str: public_b.to_string()
-                            };
-
-                            let body = serde_json::to_string(&srp_step1_response).unwrap();
-                            Ok(HttpResponse::Ok()
-                                .content_type("application/json")
-                                .body(body))
-                        }
-                        Err(err) => {
-                            Err(HttpErrorCode::UnAuthorized {message : ErrorResponse {message: err.to_string(), error_code : "unauthorized".to_string()}})
-                        }
-                    }
-                }
-                Err(err) => {
-                    match srp_session_map.lock() {
-                        Ok(mut sessions) => {
-                            sessions.remove(identity.as_str());
-                        }
-                        Err(_) => {}
-                    }
-                    Err(HttpErrorCode::UnAuthorized {message : ErrorResponse {message: err.to_string(), error_code : "unauthorized".to_string()}})
-                }
-            }
+        None => {
+            Err(HttpErrorCode::ServiceUnavailable {message : ErrorResponse {message: "too many pending logins".to_string(), error_code : "service_unavailable".to_string()}})
         }
     }
+}
 
+/// salt, verifier and group to run step 1 with.
+/// identities without srp credentials get fake but stable ones in the registration group,
+/// so step 1 looks the same whether or not the identity is registered.
+/// known limitation: users still on LEGACY_1024 answer with their group, which no unknown identity does,
+/// so those identities can be told apart. the server cannot move them without their password,
+/// they leave the legacy group on their next password change or reset
+fn srp_credentials(user: Option<UserEntity>, identity: &str, srp_config: &SrpConfiguration) -> (BigUint, BigUint, SrpGroup) {
+    match user {
+        Some(UserEntity { salt: Some(salt_str), verifier: Some(verifier_str), srp_group, .. }) => {
+            // rows created before groups were configurable have no group
+            let group = srp_group.unwrap_or(SrpGroup::LEGACY_1024);
+            let salt = convert_to_bigint(salt_str.as_bytes(), 10).unwrap();
+            let verifier = convert_to_bigint(verifier_str.as_bytes(), 10).unwrap();
+            (salt, verifier, group)
+        }
+        _ => {
+            let group = srp_config.registration_group;
+            let (salt, verifier) = srp_service::fake_credentials(srp_config.fake_credentials_secret.as_str(), identity, group);
+            (salt, verifier, group)
+        }
+    }
 }
 
 /// srp step 2 validate client m1 evidence and generate server m2 evidence
+/// every failure gets the same answer, unknown identities included
 #[post("/2")]
 pub async fn login_step_2(
     srp_req: web::Json<SrpStep2Request>,
-    pool: web::Data<Pool<MySql>>,
-    srp_session_map: web::Data<Mutex<HashMap<String, SrpServer>>>) -> Result<HttpResponse, HttpErrorCode> {
+    srp_session_store: web::Data<dyn SrpSessionStore>,
+    oidc_config: web::Data<OidcConfiguration>,
+    keys: web::Data<KeyManager>,
+    pool: web::Data<Pool<MySql>>) -> Result<HttpResponse, HttpErrorCode> {
+    let unauthorized = || HttpErrorCode::UnAuthorized {message : ErrorResponse {message: "bad credentials".to_string(), error_code : "unauthorized".to_string()}};
     let identity = srp_req.identity.clone();
-    let m1_str = srp_req.m1_str.clone();
-    match srp_session_map.lock() {
-        Ok(mut sessions) => {
-            let m1 = convert_to_bigint(m1_str.as_bytes(), 10).unwrap();
-            let session = sessions.remove(identity.as_str()).unwrap();
-            match session.step_2(m1.clone()) {
-                Ok(m2) => {
-                    let srp2response = SrpStep2Response {
-                        m2_str: m2.to_string()
-                    };
-
-                    let body = serde_json::to_string(&srp2response).unwrap();
-                    Ok(HttpResponse::Ok()
-                        .content_type("application/json")
-                        .body(body))
-                }
-                Err(err) => {
-                    Err(HttpErrorCode::UnAuthorized {message : ErrorResponse {message: err.to_string(), error_code : "unauthorized".to_string()}})
+    let scope = oidc_config.grant_scope(srp_req.scope.as_deref()).ok_or_else(invalid_scope)?;
+    let m1 = BigUint::parse_bytes(srp_req.m1_str.as_bytes(), 10)
+        .ok_or_else(unauthorized)?;
+    let handshake = srp_session_store.take(srp_req.handshake_id.as_str()).await
+        .filter(|handshake| handshake.identity == identity)
+        .ok_or_else(unauthorized)?;
+    match handshake.finish(&m1) {
+        Ok(m2) => {
+            // client evidence verified, start a user session
+            let auth_time = Utc::now().timestamp() as usize;
+            let mut user_service = UserService::new(pool.get_ref());
+            let (permissions, refresh_token) = match user_service.fetch_by_email(&identity).await {
+                Some(user) => {
+                    let ttl = Duration::seconds(oidc_config.refresh_token_ttl_seconds);
+                    (user_service.fetch_permissions(user.id.unwrap()).await,
+                     TokenService::new(pool.get_ref()).issue_refresh_token(user.id.unwrap(), SessionType::USER, &scope, None, ttl).await)
                 }
-            }
+                None => (vec![], None)
+            };
+            let jwt = oidc_config.access_token(keys.get_ref(), &identity, SessionType::USER, None, permissions, scope.clone());
+            let id_token = oidc_config.id_token(keys.get_ref(), &oidc_config.audience, &identity, srp_req.nonce.clone(), auth_time);
+            let srp2response = SrpStep2Response {
+                m2_str: m2.to_string(),
+                access_token: jwt.clone(),
+                expires_in: oidc_config.access_token_ttl_seconds,
+                refresh_token,
+                id_token,
+                scope
+            };
+
+            let body = serde_json::to_string(&srp2response).unwrap();
+            Ok(HttpResponse::Ok()
+                .header("Authorization", format!("bearer {}", jwt))
+                .content_type("application/json")
+                .body(body))
         }
-        Err(err) => {
-            Err(HttpErrorCode::UnAuthorized {message : ErrorResponse {message: err.to_string(), error_code : "unauthorized".to_string()}})
+        Err(_) => {
+            Err(unauthorized())
         }
     }
 }
 
+/// group new registrations must compute their verifier with
+#[get("/group")]
+pub async fn group_parameters(srp_config: web::Data<SrpConfiguration>) -> impl Responder {
+    let body = serde_json::to_string(&SrpGroupResponse::new(srp_config.registration_group)).unwrap();
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(body)
+}
+
+/// srp registration
+/// receive identity, salt and verifier computed client side
+/// the password itself never reaches the server
+#[post("/register")]
+pub async fn register(
+    srp_req: web::Json<SrpRegisterRequest>,
+    srp_config: web::Data<SrpConfiguration>,
+    pool: web::Data<Pool<MySql>>) -> Result<HttpResponse, HttpErrorCode> {
+    let req = srp_req.into_inner();
+    validate_registration(&req, srp_config.registration_group)?;
+
+    let mut user_service = UserService::new(pool.get_ref());
+    if user_service.fetch_by_email(&req.identity).await.is_some() {
+        return Err(HttpErrorCode::Conflict {message : ErrorResponse {message: "identity already registered".to_string(), error_code : "conflict".to_string()}});
+    }
+
+    let entity = UserEntity {
+        id: None,
+        first_name: req.first_name,
+        last_name: req.last_name,
+        email: req.identity,
+        phone_number: req.phone_number,
+        language_id: req.language_id.unwrap_or(1),
+        salt: Some(req.salt_str),
+        verifier: Some(req.verifier_str),
+        srp_group: Some(req.group)
+    };
+    match user_service.register_srp_user(entity).await {
+        None => {
+            Err(HttpErrorCode::BadRequest {message : ErrorResponse {message: "registration failed".to_string(), error_code : "bad_request".to_string()}})
+        }
+        Some(mut user) => {
+            // never echo the verifier back
+            user.verifier = None;
+            let body = serde_json::to_string(&user).unwrap();
+            Ok(HttpResponse::Created()
+                .content_type("application/json")
+                .body(body))
+        }
+    }
+}
+
+/// validate the registration payload before it reaches the database
+fn validate_registration(req: &SrpRegisterRequest, registration_group: SrpGroup) -> Result<(), HttpErrorCode> {
+    let bad_request = |message: &str| HttpErrorCode::BadRequest {message : ErrorResponse {message: message.to_string(), error_code : "bad_request".to_string()}};
+    let identity = req.identity.trim();
+    if identity.is_empty() || identity.len() > 45 || !identity.contains('@') || identity.contains(char::is_whitespace) {
+        return Err(bad_request("invalid identity"));
+    }
+    if req.phone_number.is_empty() || req.phone_number.len() > 25 {
+        return Err(bad_request("invalid phone number"));
+    }
+    validate_credentials(&req.salt_str, &req.verifier_str, req.group, registration_group)
+}
+
+/// validate a client computed salt and verifier, new credentials always use the registration group
+pub fn validate_credentials(salt_str: &String, verifier_str: &String, group: SrpGroup, registration_group: SrpGroup) -> Result<(), HttpErrorCode> {
+    let bad_request = |message: &str| HttpErrorCode::BadRequest {message : ErrorResponse {message: message.to_string(), error_code : "bad_request".to_string()}};
+    match BigUint::parse_bytes(salt_str.as_bytes(), 10) {
+        Some(salt) if !salt.is_zero() => {}
+        _ => return Err(bad_request("invalid salt"))
+    }
+    if group != registration_group {
+        return Err(bad_request("unsupported srp group"));
+    }
+    match BigUint::parse_bytes(verifier_str.as_bytes(), 10) {
+        Some(verifier) if !verifier.is_zero() && verifier < group.n() => {}
+        _ => return Err(bad_request("invalid verifier"))
+    }
+    Ok(())
+}
+
+/// srp login and registration happen before there is a session
+pub fn policy(policy: &mut RoutePolicy) {
+    policy.prefix("/srp/", RouteAccess::PUBLIC);
+}
+
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(web::scope("/srp/")
         .service(login_step_1)
-        .service(login_step_2));
+        .service(login_step_2)
+        .service(group_parameters)
+        .service(register));
 }
 
 #[derive(Deserialize, Serialize)]
@@ -139,7 +253,7 @@ struct Ass {
 #[cfg(test)]
 mod test {
     use std::ops::Add;
-    use std::sync::Mutex;
+    use std::sync::{Arc, Mutex};
     use std::thread;
     use std::time::SystemTime;
 
@@ -156,30 +270,43 @@ mod test {
     use rust_srp::{SrpClient, SrpServer};
     use std::collections::HashMap;
     use num_bigint::BigUint;
-    use crate::entities::srp::srp_entities::{SrpStep1Request, SrpStep1Response, SrpStep2Response, SrpStep2Request};
+    use crate::entities::srp::srp_entities::{SrpStep1Request, SrpStep1Response, SrpStep2Response, SrpStep2Request, SrpRegisterRequest};
     use serde::{Deserialize, Serialize};
     use serde_json;
     use rust_srp::bigint_helper::convert_to_bigint;
+    use super::{validate_registration, srp_credentials};
+    use crate::filters::route_policy::RoutePolicy;
+    use crate::entities::user_entity::UserEntity;
+    use crate::services::srp_service::{SrpConfiguration, SrpSessionStoreKind};
+    use crate::entities::srp::srp_group::SrpGroup;
+    use crate::services::srp_session_store::{SrpSessionStore, InMemorySrpSessionStore};
 
     #[actix_rt::test]
     async fn test_srp_server_flow() {
         std::env::set_var("RUST_LOG", "debug");
         std::env::set_var("RUST_BACKTRACE", "1");
         env_logger::try_init();
-        let srp_session_management:HashMap<String, SrpServer> = HashMap::new();
-        let srp_session_management = web::Data::new(Mutex::new(srp_session_management));
+        let srp_session_store: Arc<dyn SrpSessionStore> = Arc::new(InMemorySrpSessionStore::new(std::time::Duration::from_secs(60), 10));
+        let srp_session_store = web::Data::from(srp_session_store);
         let pool = PoolInstantiate::init().await;
+        let keys = web::Data::new(crate::services::key_manager::test_key_manager());
         let mut app = test::init_service(App::new()
-            .wrap(authentication_filter::AuthFilter)
+            .wrap(authentication_filter::AuthFilter::new(Arc::new({
+                let mut policy = RoutePolicy::new();
+                srp_resource::policy(&mut policy);
+                policy
+            })))
             .wrap(cors_filter::CorsFilter)
-            .app_data(srp_session_management.clone())
+            .app_data(srp_session_store.clone())
+            .app_data(web::Data::new(srp_config()))
+            .app_data(keys.clone())
+            .app_data(web::Data::new(crate::services::oidc_service::test_oidc_configuration()))
             .data(pool.clone())
             .configure(srp_resource::config)).await;
 
-        // client
-        let n = BigUint::parse_bytes(b"B97F8C656C3DF7179C2B805BBCB3A0DC4B0B6926BF66D0A3C63CF6015625CAF9A4DB4BBE7EB34253FAB0E475A6ACFAE49FD5F22C47A71B5532911B69FE7DF4F8ACEE2F7785D75866CF6D213286FC7EBBBE3BE411ECFA10A70F0C8463DC1182C6F9B6F7666C8691B3D1AB6FD78E9CBF8AAE719EA75CA02BE87AE445C698BF0413", 16).unwrap();
-        let g = BigUint::parse_bytes(b"2", 10).unwrap();
-        let mut client = SrpClient::new(n, g);
+        // client, the seeded user was registered before groups were configurable
+        let group = SrpGroup::LEGACY_1024;
+        let mut client = SrpClient::new(group.n(), group.g());
         let public_a = client.step_1("mohammedalanny@gmail.com".to_string(), "12345678".to_string());
         let srp1_request = SrpStep1Request {
             identity: format!("{}", "mohammedalanny@gmail.com"),
@@ -195,13 +322,17 @@ mod test {
             .to_request();
         let mut resp = test::read_response(&mut app, req).await;
         let srp1_response: SrpStep1Response = serde_json::from_slice(resp.as_ref()).unwrap();
+        assert_eq!(srp1_response.group.name, group);
         let salt = convert_to_bigint(srp1_response.salt_str.clone().as_bytes(), 10).unwrap();
         let public_b = convert_to_bigint(srp1_response.public_b_str.clone().as_bytes(), 10).unwrap();
 
         let m1 = client.step_2(salt, public_b).unwrap();
         let srp2_request = SrpStep2Request {
+            handshake_id: srp1_response.handshake_id.clone(),
             identity: format!("{}", "mohammedalanny@gmail.com"),
-            m1_str: m1.to_string()
+            m1_str: m1.to_string(),
+            nonce: Some("n-0S6_WzA2Mj".to_string()),
+            scope: Some("openid devices:read".to_string())
         };
 
         let req = test::TestRequest::with_header("content-type", "application/json")
@@ -211,8 +342,124 @@ mod test {
             .to_request();
         let mut resp = test::read_response(&mut app, req).await;
         let srp2_response: SrpStep2Response = serde_json::from_slice(resp.as_ref()).unwrap();
+
+        // an unknown identity gets a step 1 answer of the same shape and fails step 2 like a bad password
+        let mut ghost = SrpClient::new(SrpGroup::RFC5054_2048.n(), SrpGroup::RFC5054_2048.g());
+        let ghost_request = SrpStep1Request {
+            identity: "ghost@gmail.com".to_string(),
+            public_a_str: ghost.step_1("ghost@gmail.com".to_string(), "12345678".to_string()).unwrap().to_string()
+        };
+        let req = test::TestRequest::with_header("content-type", "application/json")
+            .uri("/srp/1")
+            .set_json(&ghost_request)
+            .method(Method::POST)
+            .to_request();
+        let ghost_response = test::read_response(&mut app, req).await;
+        let ghost_json: serde_json::Value = serde_json::from_slice(ghost_response.as_ref()).unwrap();
+        let known_json = serde_json::to_value(&srp1_response).unwrap();
+        let fields = |value: &serde_json::Value| value.as_object().unwrap().keys().cloned().collect::<Vec<String>>();
+        assert_eq!(fields(&known_json), fields(&ghost_json));
+        let req = test::TestRequest::with_header("content-type", "application/json")
+            .uri("/srp/2")
+            .set_json(&SrpStep2Request {
+                handshake_id: ghost_json["handshake_id"].as_str().unwrap().to_string(),
+                identity: "ghost@gmail.com".to_string(),
+                m1_str: "42".to_string(),
+                nonce: None,
+                scope: None
+            })
+            .method(Method::POST)
+            .to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::UNAUTHORIZED);
+
         client.step_3(convert_to_bigint(srp2_response.m2_str.as_bytes(), 10).unwrap()).unwrap();
 
+        // the session token belongs to the srp identity
+        let claims = crate::services::jwt_service::verify(&keys, &srp2_response.access_token).unwrap();
+        assert_eq!(claims.sub.unwrap(), "mohammedalanny@gmail.com");
+        assert_eq!(claims.session_type.unwrap(), SessionType::USER);
+        assert_eq!(claims.scope.unwrap(), "openid devices:read");
+        let id_claims = crate::services::jwt_service::verify(&keys, &srp2_response.id_token).unwrap();
+        assert_eq!(id_claims.sub.unwrap(), "mohammedalanny@gmail.com");
+        assert_eq!(id_claims.nonce.unwrap(), "n-0S6_WzA2Mj");
+        assert!(srp2_response.refresh_token.is_some());
+
+
+    }
+
+    #[test]
+    fn test_validate_registration() {
+        let group = SrpGroup::RFC5054_2048;
+        let salt = rust_srp::bigint_helper::generate_random_256bit_bigint();
+        let verifier = group.g().modpow(&rust_srp::compute_x(&salt, "12345678"), &group.n());
+        let mut req = SrpRegisterRequest {
+            identity: "new@gmail.com".to_string(),
+            salt_str: salt.to_string(),
+            verifier_str: verifier.to_string(),
+            group,
+            first_name: None,
+            last_name: None,
+            phone_number: "0403231145".to_string(),
+            language_id: None
+        };
+        assert!(validate_registration(&req, group).is_ok());
+
+        // only the configured registration group is accepted
+        assert!(validate_registration(&req, SrpGroup::RFC5054_4096).is_err());
+
+        req.verifier_str = "not a number".to_string();
+        assert!(validate_registration(&req, group).is_err());
+
+        req.verifier_str = "0".to_string();
+        assert!(validate_registration(&req, group).is_err());
+
+        req.verifier_str = group.n().to_string();
+        assert!(validate_registration(&req, group).is_err());
+
+        req.verifier_str = verifier.to_string();
+        req.identity = "no-at-sign".to_string();
+        assert!(validate_registration(&req, group).is_err());
+    }
+
+    fn srp_config() -> SrpConfiguration {
+        SrpConfiguration {
+            session_store: SrpSessionStoreKind::MEMORY,
+            session_ttl_seconds: 60,
+            max_pending_sessions: 10,
+            eviction_interval_seconds: 30,
+            registration_group: SrpGroup::RFC5054_2048,
+            fake_credentials_secret: "secret".to_string(),
+            fake_credentials_secret_path: None,
+            reset_token_ttl_seconds: 3600
+        }
+    }
+
+    #[test]
+    fn test_unknown_identity_credentials_look_registered() {
+        let config = srp_config();
+        let group = config.registration_group;
+        let salt = rust_srp::bigint_helper::generate_random_256bit_bigint();
+        let verifier = group.g().modpow(&rust_srp::compute_x(&salt, "12345678"), &group.n());
+        let user = UserEntity {
+            id: Some(2),
+            first_name: None,
+            last_name: None,
+            email: "known@gmail.com".to_string(),
+            phone_number: "0403231145".to_string(),
+            language_id: 1,
+            salt: Some(salt.to_string()),
+            verifier: Some(verifier.to_string()),
+            srp_group: Some(group)
+        };
+
+        let (known_salt, known_verifier, known_group) = srp_credentials(Some(user), "known@gmail.com", &config);
+        let (ghost_salt, ghost_verifier, ghost_group) = srp_credentials(None, "ghost@gmail.com", &config);
+        assert_eq!(known_group, ghost_group);
+        assert!(known_salt.bits() <= 256 && ghost_salt.bits() <= 256);
+        assert!(known_verifier < group.n() && ghost_verifier < group.n());
 
+        // a repeated probe sees the same salt, just like for a registered user
+        let (again_salt, _, _) = srp_credentials(None, "ghost@gmail.com", &config);
+        assert_eq!(ghost_salt, again_salt);
     }
 }
\ No newline at end of file
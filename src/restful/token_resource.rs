@@ -0,0 +1,58 @@
+use actix_web::{HttpResponse, post, web};
+use chrono::Duration;
+use sqlx::MySqlPool;
+
+use crate::entities::token_entities::{RefreshTokenRequest, TokenResponse};
+use crate::exceptions::error_base::{ErrorResponse, HttpErrorCode};
+use crate::filters::route_policy::{RouteAccess, RoutePolicy};
+use crate::services::key_manager::KeyManager;
+use crate::services::oidc_service::{invalid_scope, OidcConfiguration};
+use crate::services::revocation_store::RevocationStore;
+use crate::services::token_service::{RefreshOutcome, TokenService};
+use crate::services::user_service::UserService;
+
+/// swap a refresh token for a new access token and a new refresh token.
+/// the presented refresh token is used up, replaying it revokes every token of its login.
+/// permissions are read again, changes to them take effect on the next refresh.
+/// a scope in the request narrows the access token, the refresh token keeps the scope of the login.
+/// only tokens of our own logins are taken here, those of oauth clients are refreshed at /oauth/token
+#[post("/refresh")]
+pub async fn refresh(
+    req: web::Json<RefreshTokenRequest>,
+    oidc_config: web::Data<OidcConfiguration>,
+    keys: web::Data<KeyManager>,
+    revocation_store: web::Data<dyn RevocationStore>,
+    pool: web::Data<MySqlPool>) -> Result<HttpResponse, HttpErrorCode> {
+    let mut token_service = TokenService::new(pool.get_ref());
+    let ttl = Duration::seconds(oidc_config.refresh_token_ttl_seconds);
+    match token_service.rotate(&req.refresh_token, None, req.scope.as_deref(), ttl, &**revocation_store).await {
+        RefreshOutcome::Rotated { user_id, email, session_type, scope, refresh_token } => {
+            let permissions = UserService::new(pool.get_ref()).fetch_permissions(user_id).await;
+            let token_response = TokenResponse {
+                access_token: oidc_config.access_token(keys.get_ref(), &email, session_type, None, permissions, scope.clone()),
+                token_type: "bearer".to_string(),
+                expires_in: oidc_config.access_token_ttl_seconds,
+                refresh_token: Some(refresh_token),
+                id_token: None,
+                scope: Some(scope),
+            };
+            Ok(HttpResponse::Ok()
+                .header("Cache-Control", "no-store")
+                .json(token_response))
+        }
+        RefreshOutcome::InvalidScope => Err(invalid_scope()),
+        RefreshOutcome::Reused | RefreshOutcome::Invalid => {
+            Err(HttpErrorCode::UnAuthorized {message : ErrorResponse {message: "invalid refresh token".to_string(), error_code : "unauthorized".to_string()}})
+        }
+    }
+}
+
+/// the refresh token is the credential, there is no valid access token to send
+pub fn policy(policy: &mut RoutePolicy) {
+    policy.exact("/token/refresh", RouteAccess::PUBLIC);
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::scope("/token")
+        .service(refresh));
+}
@@ -2,8 +2,9 @@ use std::borrow::BorrowMut;
 use std::ops::Add;
 use std::sync::{Mutex, RwLock};
 
-use actix_web::{App, Error, get, guard, HttpRequest, HttpResponse, HttpServer, post, Responder, web};
+use actix_web::{App, delete, Error, get, guard, HttpRequest, HttpResponse, HttpServer, post, Responder, web};
 use actix_web::body::Body;
+use actix_web::http::Method;
 use actix_web::web::Data;
 use chrono::{Duration, Utc};
 use futures::future::{ready, Ready};
@@ -15,13 +16,29 @@ use uuid::Uuid;
 
 use crate::{main, UserPrinciple};
 use crate::daos::user_dao;
-use crate::services::jwt_service::SessionType;
+use crate::services::jwt_service::Permission;
 use crate::services::user_service::UserService;
 
 use crate::exceptions::error_base::{ErrorResponse, HttpErrorCode};
 use crate::filters::authentication_filter::{ContentTypeHeader, MethodAllowed};
+use crate::filters::authorization_filter::{RequirePermission, RequireScope};
+use crate::entities::srp::srp_entities::{PasswordChangeRequest, PasswordResetConfirmation, PasswordResetRequest, PasswordResetResponse};
+use crate::entities::srp::srp_group::SrpGroup;
+use crate::filters::route_policy::{RouteAccess, RoutePolicy};
+use crate::restful::srp_resource::validate_credentials;
+use crate::services::srp_service::SrpConfiguration;
+use crate::services::srp_session_store::SrpSessionStore;
+use crate::services::revocation_store::RevocationStore;
+use crate::services::token_service::TokenService;
+use crate::ouath::oauth::LoginState;
+use crate::ouath::provider::OAuthProviders;
+use crate::restful::social_login_resource::{provider, start_login};
+use crate::services::external_identity_service::{ExternalIdentityService, UnlinkOutcome};
+use crate::services::oidc_service::OidcConfiguration;
+use crate::entities::user_entity::UserEntity;
+use num_bigint::BigUint;
 
-#[get("/profile")]
+/// registered for tokens granted the profile scope only
 pub async fn profile(user: UserPrinciple, pool: web::Data<MySqlPool>) -> impl Responder {
     let pool_ref = pool.get_ref();
     //let result = &mut pool.acquire().await.unwrap();
@@ -30,7 +47,208 @@ pub async fn profile(user: UserPrinciple, pool: web::Data<MySqlPool>) -> impl Re
     option
 }
 
+/// replace the srp credentials of the logged in user and end all of its sessions.
+/// needs a fresh srp proof for the current password
+#[post("/password")]
+pub async fn change_password(
+    user: UserPrinciple,
+    req: web::Json<PasswordChangeRequest>,
+    srp_session_store: web::Data<dyn SrpSessionStore>,
+    srp_config: web::Data<SrpConfiguration>,
+    revocation_store: web::Data<dyn RevocationStore>,
+    pool: web::Data<MySqlPool>) -> Result<HttpResponse, HttpErrorCode> {
+    let unauthorized = || HttpErrorCode::UnAuthorized {message : ErrorResponse {message: "bad credentials".to_string(), error_code : "unauthorized".to_string()}};
+    let req = req.into_inner();
+    validate_credentials(&req.salt_str, &req.verifier_str, req.group, srp_config.registration_group)?;
+
+    let email = user.email.unwrap();
+    let mut user_service = UserService::new(pool.get_ref());
+    let entity = user_service.fetch_by_email(&email).await.ok_or_else(unauthorized)?;
+    let m1 = BigUint::parse_bytes(req.m1_str.as_bytes(), 10).ok_or_else(unauthorized)?;
+    let handshake = srp_session_store.take(req.handshake_id.as_str()).await
+        .filter(|handshake| handshake.identity == email)
+        .ok_or_else(unauthorized)?;
+    handshake.finish(&m1).map_err(|_| unauthorized())?;
+    replace_credentials(&mut user_service, &entity, &req.salt_str, &req.verifier_str, req.group, &**revocation_store, pool.get_ref()).await
+}
+
+/// set new srp credentials with a reset token from /user/password/reset, there is no session
+/// since the password is forgotten. ends all sessions of the user like a password change
+#[post("/password/reset/confirm")]
+pub async fn confirm_password_reset(
+    req: web::Json<PasswordResetConfirmation>,
+    srp_config: web::Data<SrpConfiguration>,
+    revocation_store: web::Data<dyn RevocationStore>,
+    pool: web::Data<MySqlPool>) -> Result<HttpResponse, HttpErrorCode> {
+    // unknown identities and bad tokens answer alike
+    let unauthorized = || HttpErrorCode::UnAuthorized {message : ErrorResponse {message: "bad credentials".to_string(), error_code : "unauthorized".to_string()}};
+    let req = req.into_inner();
+    validate_credentials(&req.salt_str, &req.verifier_str, req.group, srp_config.registration_group)?;
+
+    let mut user_service = UserService::new(pool.get_ref());
+    let entity = user_service.fetch_by_email(&req.identity).await.ok_or_else(unauthorized)?;
+    if !user_service.consume_reset_token(entity.id.unwrap(), &req.reset_token).await {
+        return Err(unauthorized());
+    }
+    replace_credentials(&mut user_service, &entity, &req.salt_str, &req.verifier_str, req.group, &**revocation_store, pool.get_ref()).await
+}
+
+/// store the credentials and end every session and refresh token of the user
+async fn replace_credentials(
+    user_service: &mut UserService<'_>,
+    entity: &UserEntity,
+    salt: &String,
+    verifier: &String,
+    group: SrpGroup,
+    revocation_store: &dyn RevocationStore,
+    pool: &MySqlPool) -> Result<HttpResponse, HttpErrorCode> {
+    if !user_service.change_credentials(&entity.email, salt, verifier, group).await {
+        return Err(HttpErrorCode::BadRequest {message : ErrorResponse {message: "password change failed".to_string(), error_code : "bad_request".to_string()}});
+    }
+    revocation_store.revoke_subject(entity.email.as_str()).await;
+    TokenService::new(pool).revoke_user(entity.id.unwrap()).await;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// issue a password reset token for a user, registered for holders of the GLOBAL_UPDATE permission.
+/// the token is handed to the user out of band and used with /user/password/reset/confirm
+pub async fn issue_password_reset(
+    req: web::Json<PasswordResetRequest>,
+    srp_config: web::Data<SrpConfiguration>,
+    pool: web::Data<MySqlPool>) -> Result<HttpResponse, HttpErrorCode> {
+    let mut user_service = UserService::new(pool.get_ref());
+    let entity = user_service.fetch_by_email(&req.identity).await
+        .ok_or_else(|| HttpErrorCode::BadRequest {message : ErrorResponse {message: "unknown identity".to_string(), error_code : "bad_request".to_string()}})?;
+    let ttl = Duration::seconds(srp_config.reset_token_ttl_seconds as i64);
+    match user_service.issue_reset_token(entity.id.unwrap(), ttl).await {
+        Some((reset_token, expires_at)) => {
+            let body = serde_json::to_string(&PasswordResetResponse { reset_token, expires_at }).unwrap();
+            Ok(HttpResponse::Created()
+                .content_type("application/json")
+                .body(body))
+        }
+        None => {
+            Err(HttpErrorCode::BadRequest {message : ErrorResponse {message: "reset token not issued".to_string(), error_code : "bad_request".to_string()}})
+        }
+    }
+}
+
+/// the user behind a session, service and guest sessions have no provider accounts to link
+async fn session_user(user: &UserPrinciple, pool: &MySqlPool) -> Result<UserEntity, HttpErrorCode> {
+    let forbidden = || HttpErrorCode::Forbidden {message : ErrorResponse {message: "only users have linked accounts".to_string(), error_code : "forbidden".to_string()}};
+    let email = user.email.as_ref().ok_or_else(forbidden)?;
+    UserService::new(pool).fetch_by_email(email).await.ok_or_else(forbidden)
+}
+
+/// the provider accounts linked to the logged in user
+#[get("/identities")]
+pub async fn identities(user: UserPrinciple, pool: web::Data<MySqlPool>) -> Result<HttpResponse, HttpErrorCode> {
+    let entity = session_user(&user, pool.get_ref()).await?;
+    let identities = ExternalIdentityService::new(pool.get_ref()).fetch_by_user_id(entity.id.unwrap()).await;
+    Ok(HttpResponse::Ok().json(identities))
+}
+
+/// start linking an account of the provider to the logged in user, answered with the provider url like login1.
+/// the provider's callback then links the account instead of logging in with it
+#[get("/identities/{provider}/link")]
+pub async fn link_identity(
+    http_req: HttpRequest,
+    user: UserPrinciple,
+    path: web::Path<String>,
+    providers: web::Data<OAuthProviders>,
+    oidc_config: web::Data<OidcConfiguration>,
+    pool: web::Data<MySqlPool>) -> Result<HttpResponse, HttpErrorCode> {
+    let auth_service = provider(providers.get_ref(), &path)?;
+    let entity = session_user(&user, pool.get_ref()).await?;
+    let login_state = LoginState { nonce: None, scope: user.scopes.join(" "), authorization: None, link_user_id: entity.id };
+    let (url, cookie) = start_login(&http_req, pool.get_ref(), oidc_config.get_ref(), &path, auth_service, login_state).await?;
+    Ok(HttpResponse::Ok().cookie(cookie).body(url))
+}
+
+/// unlink the user's account of the provider, the last one stays unless the user has a password
+#[delete("/identities/{provider}")]
+pub async fn unlink_identity(
+    user: UserPrinciple,
+    path: web::Path<String>,
+    pool: web::Data<MySqlPool>) -> Result<HttpResponse, HttpErrorCode> {
+    let entity = session_user(&user, pool.get_ref()).await?;
+    match ExternalIdentityService::new(pool.get_ref()).unlink(&entity, &path).await {
+        UnlinkOutcome::Unlinked => Ok(HttpResponse::NoContent().finish()),
+        UnlinkOutcome::NotLinked => {
+            Err(HttpErrorCode::BadRequest {message : ErrorResponse {message: format!("no {} account linked", path), error_code : "not_linked".to_string()}})
+        }
+        UnlinkOutcome::LastSignIn => {
+            Err(HttpErrorCode::Conflict {message : ErrorResponse {message: "set a password before unlinking the last provider account".to_string(), error_code : "last_sign_in".to_string()}})
+        }
+    }
+}
+
+/// confirming a reset is for users who cannot log in, everything else needs a session
+pub fn policy(policy: &mut RoutePolicy) {
+    policy.method(Method::POST, "/user/password/reset/confirm", RouteAccess::PUBLIC);
+}
+
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(web::scope("/user/")
-        .service(profile));
-}
\ No newline at end of file
+        .service(web::resource("/profile")
+            .wrap(RequireScope("profile"))
+            .route(web::get().to(profile)))
+        .service(change_password)
+        .service(confirm_password_reset)
+        .service(identities)
+        .service(link_identity)
+        .service(unlink_identity)
+        .service(web::resource("/password/reset")
+            .wrap(RequirePermission(Permission::GLOBAL_UPDATE))
+            .route(web::post().to(issue_password_reset))));
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use actix_web::{App, test};
+    use actix_web::http::StatusCode;
+
+    use crate::entities::srp::srp_entities::PasswordResetRequest;
+    use crate::filters::authentication_filter::AuthFilter;
+    use crate::services::key_manager::{test_claims, test_key_manager, test_token};
+
+    use super::*;
+
+    #[actix_rt::test]
+    async fn test_identities_need_a_session() {
+        let pool = MySqlPool::connect_lazy("mysql://nobody@localhost/iot").unwrap();
+        let mut app = test::init_service(App::new()
+            .data(pool)
+            .configure(config)).await;
+        let req = test::TestRequest::delete().uri("/user/identities/google").to_request();
+        let res = test::call_service(&mut app, req).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        let req = test::TestRequest::get().uri("/user/identities").to_request();
+        let res = test::call_service(&mut app, req).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_rt::test]
+    async fn test_reset_tokens_need_permission() {
+        let mut app = test::init_service(App::new()
+            .wrap(AuthFilter::new(Arc::new(RoutePolicy::new())))
+            .app_data(web::Data::new(test_key_manager()))
+            .configure(config)).await;
+        let req = test::TestRequest::post().uri("/user/password/reset")
+            .header("Authorization", format!("bearer {}", test_token(test_claims())))
+            .set_json(&PasswordResetRequest { identity: "ahmed@gmail.com".to_string() })
+            .to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn test_reset_confirmation_is_public() {
+        let mut route_policy = RoutePolicy::new();
+        policy(&mut route_policy);
+        assert_eq!(route_policy.access(&Method::POST, "/user/password/reset/confirm"), RouteAccess::PUBLIC);
+        assert_eq!(route_policy.access(&Method::POST, "/user/password"), RouteAccess::AUTHENTICATED);
+        assert_eq!(route_policy.access(&Method::POST, "/user/password/reset"), RouteAccess::AUTHENTICATED);
+    }
+}
@@ -0,0 +1,81 @@
+use actix_web::{get, http, HttpResponse, web};
+
+use crate::entities::jwk_entities::JwkSet;
+use crate::exceptions::error_base::HttpErrorCode;
+use crate::filters::route_policy::{RouteAccess, RoutePolicy};
+use crate::services::key_manager::KeyManager;
+use crate::services::oidc_service::OidcConfiguration;
+
+/// public keys other services verify our tokens with.
+/// HMAC keys are shared secrets and are never listed, retiring keys stay listed until their rotation window ends
+#[get("/jwks.json")]
+pub async fn jwks(keys: web::Data<KeyManager>) -> Result<HttpResponse, HttpErrorCode> {
+    let jwk_set = JwkSet {
+        keys: keys.verification_keys().iter()
+            .filter(|key| !key.is_symmetric())
+            .filter_map(|key| key.jwk())
+            .collect()
+    };
+    Ok(HttpResponse::Ok()
+        .set_header(http::header::CACHE_CONTROL, "public, max-age=300")
+        .json(jwk_set))
+}
+
+/// OpenID Provider metadata for off-the-shelf OIDC clients
+#[get("/openid-configuration")]
+pub async fn openid_configuration(
+    oidc_config: web::Data<OidcConfiguration>,
+    keys: web::Data<KeyManager>) -> Result<HttpResponse, HttpErrorCode> {
+    Ok(HttpResponse::Ok()
+        .set_header(http::header::CACHE_CONTROL, "public, max-age=300")
+        .json(oidc_config.discovery_document(keys.get_ref())))
+}
+
+/// discovery documents are public by definition
+pub fn policy(policy: &mut RoutePolicy) {
+    policy.prefix("/.well-known/", RouteAccess::PUBLIC);
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::scope("/.well-known")
+        .service(jwks)
+        .service(openid_configuration));
+}
+
+#[cfg(test)]
+mod test {
+    use actix_web::{App, test};
+
+    use crate::entities::jwk_entities::JwkSet;
+    use crate::entities::oidc_entities::OidcDiscoveryDocument;
+    use crate::services::key_manager::test_key_manager;
+    use crate::services::oidc_service::test_oidc_configuration;
+
+    use super::*;
+
+    #[actix_rt::test]
+    async fn test_jwks_hides_symmetric_keys() {
+        let mut app = test::init_service(App::new()
+            .app_data(web::Data::new(test_key_manager()))
+            .configure(config)).await;
+        let req = test::TestRequest::get().uri("/.well-known/jwks.json").to_request();
+        let res = test::call_service(&mut app, req).await;
+        assert!(res.status().is_success());
+        assert_eq!(res.headers().get(http::header::CACHE_CONTROL).unwrap(), "public, max-age=300");
+        let jwk_set: JwkSet = test::read_body_json(res).await;
+        assert!(jwk_set.keys.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn test_openid_configuration() {
+        let mut app = test::init_service(App::new()
+            .app_data(web::Data::new(test_key_manager()))
+            .app_data(web::Data::new(test_oidc_configuration()))
+            .configure(config)).await;
+        let req = test::TestRequest::get().uri("/.well-known/openid-configuration").to_request();
+        let document: OidcDiscoveryDocument = test::read_response_json(&mut app, req).await;
+        assert_eq!(document.issuer, "https://infotamia.com");
+        assert_eq!(document.jwks_uri, "https://infotamia.com/.well-known/jwks.json");
+        assert_eq!(document.id_token_signing_alg_values_supported, vec!["HS256".to_string()]);
+    }
+}
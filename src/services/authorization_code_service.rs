@@ -0,0 +1,94 @@
+use chrono::{Duration, Utc};
+use log::warn;
+use sha2::{Digest, Sha256};
+use sqlx::MySqlPool;
+
+use crate::daos::authorization_code_dao::AuthorizationCodeDao;
+use crate::entities::authorization_code_entity::AuthorizationCodeEntity;
+use crate::entities::authorization_entities::PendingAuthorization;
+use crate::services::token_service::{consume_once, generate_token, hash_token};
+
+pub struct AuthorizationCodeService<'a> {
+    authorization_code_dao: AuthorizationCodeDao<'a>
+}
+
+impl <'a> AuthorizationCodeService<'a> {
+    pub fn new(conn: &'a MySqlPool) -> Self {
+        AuthorizationCodeService {
+            authorization_code_dao: AuthorizationCodeDao::new(conn)
+        }
+    }
+
+    /// issue a one time code for the user that answered the authorization request, only its hash is stored
+    pub async fn issue(&mut self, authorization: &PendingAuthorization, user_id: u32, auth_time: i64, ttl: Duration) -> Option<String> {
+        let code = generate_token();
+        let entity = AuthorizationCodeEntity {
+            id: None,
+            code_hash: hash_token(&code),
+            client_id: authorization.client_id.clone(),
+            user_id,
+            email: None,
+            redirect_uri: authorization.redirect_uri.clone(),
+            scope: authorization.scope.clone(),
+            code_challenge: authorization.code_challenge.clone(),
+            nonce: authorization.nonce.clone(),
+            auth_time,
+            expires_at: Utc::now().timestamp() + ttl.num_seconds(),
+            used_at: None,
+        };
+        if self.authorization_code_dao.insert_one(&entity).await {
+            Some(code)
+        } else {
+            None
+        }
+    }
+
+    /// use up a code, None unless it is live, was issued to the client for the redirect_uri
+    /// and the verifier matches its challenge. a failed exchange still uses the code up
+    pub async fn redeem(&mut self, code: &String, client_id: &str, redirect_uri: &str, code_verifier: &str) -> Option<AuthorizationCodeEntity> {
+        let now = Utc::now().timestamp();
+        let entity = self.authorization_code_dao.find_by_hash(&hash_token(code)).await?;
+        if entity.expires_at <= now {
+            return None;
+        }
+        if !consume_once(entity.used_at, self.authorization_code_dao.mark_used(entity.id?, now)).await {
+            warn!("authorization code of client {} presented twice", entity.client_id);
+            return None;
+        }
+        let verified = entity.client_id == client_id
+            && entity.redirect_uri == redirect_uri
+            && valid_code_verifier(code_verifier)
+            && openssl::memcmp::eq(pkce_challenge(code_verifier).as_bytes(), entity.code_challenge.as_bytes());
+        if verified {
+            Some(entity)
+        } else {
+            None
+        }
+    }
+}
+
+/// S256 code_challenge of a code_verifier, RFC 7636 4.2
+pub fn pkce_challenge(code_verifier: &str) -> String {
+    base64::encode_config(Sha256::digest(code_verifier.as_bytes()), base64::URL_SAFE_NO_PAD)
+}
+
+/// 43 to 128 unreserved characters, RFC 7636 4.1
+fn valid_code_verifier(code_verifier: &str) -> bool {
+    (43..=128).contains(&code_verifier.len())
+        && code_verifier.chars().all(|c| c.is_ascii_alphanumeric() || "-._~".contains(c))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pkce_challenge() {
+        // the example of RFC 7636 appendix B
+        let code_verifier = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+        assert_eq!(pkce_challenge(code_verifier), "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM");
+        assert!(valid_code_verifier(code_verifier));
+        assert!(!valid_code_verifier("too-short"));
+        assert!(!valid_code_verifier(&format!("{}+", code_verifier)));
+    }
+}
@@ -0,0 +1,39 @@
+use chrono::Utc;
+use sqlx::MySqlPool;
+
+use crate::daos::authorization_code_dao::AuthorizationCodeDao;
+use crate::daos::device_authorization_dao::DeviceAuthorizationDao;
+use crate::daos::login_state_dao::LoginStateDao;
+use crate::daos::password_reset_dao::PasswordResetDao;
+use crate::daos::refresh_token_dao::RefreshTokenDao;
+
+/// removes the rows of the short lived grants and tokens once they expired, nothing reads them after
+pub struct CleanupService<'a> {
+    authorization_code_dao: AuthorizationCodeDao<'a>,
+    device_authorization_dao: DeviceAuthorizationDao<'a>,
+    login_state_dao: LoginStateDao<'a>,
+    password_reset_dao: PasswordResetDao<'a>,
+    refresh_token_dao: RefreshTokenDao<'a>,
+}
+
+impl <'a> CleanupService<'a> {
+    pub fn new(conn: &'a MySqlPool) -> Self {
+        CleanupService {
+            authorization_code_dao: AuthorizationCodeDao::new(conn),
+            device_authorization_dao: DeviceAuthorizationDao::new(conn),
+            login_state_dao: LoginStateDao::new(conn),
+            password_reset_dao: PasswordResetDao::new(conn),
+            refresh_token_dao: RefreshTokenDao::new(conn),
+        }
+    }
+
+    /// returns how many rows were deleted
+    pub async fn delete_expired(&mut self) -> u64 {
+        let now = Utc::now().timestamp();
+        self.authorization_code_dao.delete_expired(now).await
+            + self.device_authorization_dao.delete_expired(now).await
+            + self.login_state_dao.delete_expired(now).await
+            + self.password_reset_dao.delete_expired(now).await
+            + self.refresh_token_dao.delete_expired(now).await
+    }
+}
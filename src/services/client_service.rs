@@ -0,0 +1,73 @@
+use actix_web::http::HeaderMap;
+use sqlx::MySqlPool;
+
+use crate::daos::oauth_client_dao::OAuthClientDao;
+use crate::entities::oauth_client_entity::OAuthClientEntity;
+use crate::services::token_service::hash_token;
+
+pub struct ClientService<'a> {
+    oauth_client_dao: OAuthClientDao<'a>
+}
+
+impl <'a> ClientService<'a> {
+    pub fn new(conn: &'a MySqlPool) -> Self {
+        ClientService {
+            oauth_client_dao: OAuthClientDao::new(conn)
+        }
+    }
+
+    /// the registered client when the secret matches, None for unknown clients, wrong secrets and public clients
+    pub async fn authenticate(&mut self, client_id: &String, client_secret: &String) -> Option<OAuthClientEntity> {
+        let client = self.oauth_client_dao.find_by_client_id(client_id).await?;
+        let matches = match client.secret_hash {
+            Some(ref secret_hash) => openssl::memcmp::eq(secret_hash.as_bytes(), hash_token(client_secret).as_bytes()),
+            None => false
+        };
+        if matches {
+            Some(client)
+        } else {
+            None
+        }
+    }
+
+    /// a registered client by id alone, for public clients and the authorization endpoint
+    pub async fn find(&mut self, client_id: &String) -> Option<OAuthClientEntity> {
+        self.oauth_client_dao.find_by_client_id(client_id).await
+    }
+}
+
+/// client id and secret from an HTTP basic Authorization header, RFC 6749 2.3.1
+pub fn basic_credentials(headers: &HeaderMap) -> Option<(String, String)> {
+    let auth_value = headers.get("Authorization")?.to_str().ok()?;
+    if auth_value.len() < 6 || !auth_value[..6].eq_ignore_ascii_case("basic ") {
+        return None;
+    }
+    let decoded = String::from_utf8(base64::decode(auth_value[6..].trim()).ok()?).ok()?;
+    let mut parts = decoded.splitn(2, ':');
+    let client_id = parts.next()?.to_string();
+    let client_secret = parts.next()?.to_string();
+    Some((client_id, client_secret))
+}
+
+#[cfg(test)]
+mod test {
+    use actix_web::http::{HeaderName, HeaderValue};
+
+    use super::*;
+
+    fn headers(authorization: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(HeaderName::from_static("authorization"), HeaderValue::from_str(authorization).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_basic_credentials() {
+        let encoded = base64::encode("gateway:s3cr:et");
+        assert_eq!(basic_credentials(&headers(&format!("Basic {}", encoded))),
+                   Some(("gateway".to_string(), "s3cr:et".to_string())));
+        assert_eq!(basic_credentials(&headers(&format!("bearer {}", encoded))), None);
+        assert_eq!(basic_credentials(&headers("Basic not-base64!")), None);
+        assert_eq!(basic_credentials(&headers(&format!("Basic {}", base64::encode("no-colon")))), None);
+    }
+}
@@ -0,0 +1,161 @@
+use chrono::{Duration, Utc};
+use rand::Rng;
+use sqlx::MySqlPool;
+
+use crate::daos::device_authorization_dao::DeviceAuthorizationDao;
+use crate::entities::device_authorization_entity::DeviceAuthorizationEntity;
+use crate::services::token_service::{consume_once, generate_token, hash_token};
+
+/// RFC 8628 6.1, no vowels so no words are spelled, no digits so nothing reads ambiguously
+const USER_CODE_CHARACTERS: &[u8] = b"BCDFGHJKLMNPQRSTVWXZ";
+
+/// added to the poll interval of a device that polls too fast, RFC 8628 3.5
+const SLOW_DOWN_SECONDS: i64 = 5;
+
+/// user codes are short, a new grant may draw the code of a live one and is retried with another
+const USER_CODE_ATTEMPTS: usize = 3;
+
+/// result of a device polling with its device code
+#[derive(Debug, PartialEq)]
+pub enum DevicePollOutcome {
+    /// approved by the user, the grant is used up now
+    Approved { user_id: u32, email: String, scope: String, auth_time: i64 },
+    Pending,
+    /// polled before the interval passed, the interval is raised
+    SlowDown,
+    Denied,
+    Expired,
+    /// unknown, of another client or already exchanged
+    Invalid,
+}
+
+pub struct DeviceAuthorizationService<'a> {
+    device_authorization_dao: DeviceAuthorizationDao<'a>
+}
+
+impl <'a> DeviceAuthorizationService<'a> {
+    pub fn new(conn: &'a MySqlPool) -> Self {
+        DeviceAuthorizationService {
+            device_authorization_dao: DeviceAuthorizationDao::new(conn)
+        }
+    }
+
+    /// start a grant for the client, returns the device code and the user code shown to the user.
+    /// only the hash of the device code is stored, the user code is short lived and typed by hand
+    pub async fn start(&mut self, client_id: &str, scope: &str, ttl: Duration, poll_interval: i64) -> Option<(String, String)> {
+        for _ in 0..USER_CODE_ATTEMPTS {
+            if let Some(started) = self.try_start(client_id, scope, ttl, poll_interval).await {
+                return Some(started);
+            }
+        }
+        None
+    }
+
+    async fn try_start(&mut self, client_id: &str, scope: &str, ttl: Duration, poll_interval: i64) -> Option<(String, String)> {
+        let device_code = generate_token();
+        let user_code = generate_user_code();
+        let entity = DeviceAuthorizationEntity {
+            id: None,
+            device_code_hash: hash_token(&device_code),
+            user_code: user_code.clone(),
+            client_id: client_id.to_string(),
+            scope: scope.to_string(),
+            user_id: None,
+            email: None,
+            approved_at: None,
+            denied_at: None,
+            poll_interval,
+            last_polled_at: None,
+            expires_at: Utc::now().timestamp() + ttl.num_seconds(),
+            used_at: None,
+        };
+        if self.device_authorization_dao.insert_one(&entity).await {
+            Some((device_code, format_user_code(&user_code)))
+        } else {
+            None
+        }
+    }
+
+    /// the live grant a user code was shown for, typed with or without dash and in any case
+    pub async fn find_pending(&mut self, user_code: &str) -> Option<DeviceAuthorizationEntity> {
+        let entity = self.device_authorization_dao.find_by_user_code(&normalize_user_code(user_code)).await?;
+        let pending = entity.approved_at.is_none() && entity.denied_at.is_none() && entity.expires_at > Utc::now().timestamp();
+        if pending {
+            Some(entity)
+        } else {
+            None
+        }
+    }
+
+    /// approve or deny a pending grant in the name of the user
+    pub async fn answer(&mut self, grant: &DeviceAuthorizationEntity, user_id: u32, approve: bool) -> bool {
+        match grant.id {
+            Some(id) => self.device_authorization_dao.answer(id, user_id, approve, Utc::now().timestamp()).await,
+            None => false
+        }
+    }
+
+    pub async fn poll(&mut self, device_code: &String, client_id: &str) -> DevicePollOutcome {
+        let now = Utc::now().timestamp();
+        let entity = match self.device_authorization_dao.find_by_device_code_hash(&hash_token(device_code)).await {
+            Some(entity) if entity.client_id == client_id && entity.used_at.is_none() => entity,
+            _ => return DevicePollOutcome::Invalid
+        };
+        let id = entity.id.unwrap();
+        if entity.expires_at <= now {
+            return DevicePollOutcome::Expired;
+        }
+        let too_fast = entity.last_polled_at.is_some_and(|last_polled_at| now - last_polled_at < entity.poll_interval);
+        let poll_interval = if too_fast { entity.poll_interval + SLOW_DOWN_SECONDS } else { entity.poll_interval };
+        self.device_authorization_dao.record_poll(id, poll_interval, now).await;
+        if too_fast {
+            return DevicePollOutcome::SlowDown;
+        }
+        if entity.denied_at.is_some() {
+            return DevicePollOutcome::Denied;
+        }
+        match (entity.approved_at, entity.user_id, entity.email) {
+            (Some(approved_at), Some(user_id), Some(email)) => {
+                if !consume_once(entity.used_at, self.device_authorization_dao.mark_used(id, now)).await {
+                    return DevicePollOutcome::Invalid;
+                }
+                DevicePollOutcome::Approved { user_id, email, scope: entity.scope, auth_time: approved_at }
+            }
+            _ => DevicePollOutcome::Pending
+        }
+    }
+}
+
+/// eight characters, about 34 bits, enough for codes that live minutes and are rate limited by polling
+fn generate_user_code() -> String {
+    let mut rng = rand::thread_rng();
+    (0..8).map(|_| USER_CODE_CHARACTERS[rng.gen_range(0..USER_CODE_CHARACTERS.len())] as char).collect()
+}
+
+/// shown as BCDF-GHJK
+fn format_user_code(user_code: &str) -> String {
+    format!("{}-{}", &user_code[..4], &user_code[4..])
+}
+
+fn normalize_user_code(user_code: &str) -> String {
+    user_code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_user_code() {
+        let user_code = generate_user_code();
+        assert_eq!(user_code.len(), 8);
+        assert!(user_code.bytes().all(|c| USER_CODE_CHARACTERS.contains(&c)));
+        let shown = format_user_code(&user_code);
+        assert_eq!(shown.len(), 9);
+        assert_eq!(normalize_user_code(&shown.to_lowercase()), user_code);
+        assert_eq!(normalize_user_code(" wdjb-mjht "), "WDJBMJHT");
+    }
+}
@@ -0,0 +1,142 @@
+use sqlx::MySqlPool;
+
+use crate::daos::external_identity_dao::ExternalIdentityDao;
+use crate::daos::user_dao::UserDao;
+use crate::entities::external_identity_entity::ExternalIdentityEntity;
+use crate::entities::user_entity::UserEntity;
+use crate::ouath::oauth::ExternalAccount;
+
+/// result of a social login or of linking a provider account
+#[derive(Debug)]
+pub enum IdentityOutcome {
+    /// the user of the provider account, signed up just now when the account was new
+    SignedIn(UserEntity),
+    /// a new provider account with the email of an existing user, only that user can link it
+    EmailTaken,
+    /// linked to the user who asked for it
+    Linked,
+    /// the provider does not vouch for the email, it may not sign up or be linked
+    UnverifiedEmail,
+    /// the provider account belongs to another user, or the user has another account of the provider
+    Conflict,
+    Failed,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum UnlinkOutcome {
+    Unlinked,
+    NotLinked,
+    /// the user has no password and no other provider to log in with
+    LastSignIn,
+}
+
+pub struct ExternalIdentityService<'a> {
+    external_identity_dao: ExternalIdentityDao<'a>,
+    user_dao: UserDao<'a>
+}
+
+impl <'a> ExternalIdentityService<'a> {
+    pub fn new(conn: &'a MySqlPool) -> Self {
+        ExternalIdentityService {
+            external_identity_dao: ExternalIdentityDao::new(conn),
+            user_dao: UserDao::new(conn)
+        }
+    }
+
+    /// the user a provider account logs in as. a linked account is found by its subject whatever its
+    /// email says now, a new one signs up when its email is verified and no user has it yet.
+    /// it never joins an existing user, whoever controls the provider account would take the user over
+    pub async fn sign_in(&mut self, provider: &str, account: &ExternalAccount) -> IdentityOutcome {
+        if let Some(identity) = self.external_identity_dao.find_by_subject(provider, &account.subject).await {
+            self.external_identity_dao.update_login(identity.id.unwrap(), &account.email, &account.access_token).await;
+            return match self.user_dao.find_by_id(identity.user_id).await {
+                Some(user) => IdentityOutcome::SignedIn(user),
+                None => IdentityOutcome::Failed
+            };
+        }
+        // an unverified email would take over the account of whoever owns it
+        if !account.email_verified {
+            return IdentityOutcome::UnverifiedEmail;
+        }
+        if self.user_dao.find_by_email(&account.email).await.is_some() {
+            return IdentityOutcome::EmailTaken;
+        }
+        let user = match self.user_dao.insert_one(UserEntity::from_external_account(account)).await {
+            Some(user) => user,
+            None => return IdentityOutcome::Failed
+        };
+        if self.external_identity_dao.insert_one(&to_identity(user.id.unwrap(), provider, account)).await {
+            IdentityOutcome::SignedIn(user)
+        } else {
+            // a user without the identity could never sign in, and its email would block the next try
+            self.user_dao.delete_one(user.id.unwrap()).await;
+            IdentityOutcome::Conflict
+        }
+    }
+
+    /// link the provider account to a signed in user, linking it again only refreshes it
+    pub async fn link(&mut self, user_id: u32, provider: &str, account: &ExternalAccount) -> IdentityOutcome {
+        if !account.email_verified {
+            return IdentityOutcome::UnverifiedEmail;
+        }
+        match self.external_identity_dao.find_by_subject(provider, &account.subject).await {
+            Some(identity) if identity.user_id == user_id => {
+                self.external_identity_dao.update_login(identity.id.unwrap(), &account.email, &account.access_token).await;
+                IdentityOutcome::Linked
+            }
+            Some(_) => IdentityOutcome::Conflict,
+            None if self.external_identity_dao.insert_one(&to_identity(user_id, provider, account)).await => IdentityOutcome::Linked,
+            None => IdentityOutcome::Conflict
+        }
+    }
+
+    pub async fn unlink(&mut self, user: &UserEntity, provider: &str) -> UnlinkOutcome {
+        let user_id = user.id.unwrap();
+        let identities = self.external_identity_dao.find_by_user_id(user_id).await;
+        if !identities.iter().any(|identity| identity.provider == provider) {
+            return UnlinkOutcome::NotLinked;
+        }
+        if !keeps_a_sign_in(user, identities.len()) {
+            return UnlinkOutcome::LastSignIn;
+        }
+        if self.external_identity_dao.delete_one(user_id, provider).await {
+            UnlinkOutcome::Unlinked
+        } else {
+            UnlinkOutcome::NotLinked
+        }
+    }
+
+    pub async fn fetch_by_user_id(&mut self, user_id: u32) -> Vec<ExternalIdentityEntity> {
+        self.external_identity_dao.find_by_user_id(user_id).await
+    }
+}
+
+fn to_identity(user_id: u32, provider: &str, account: &ExternalAccount) -> ExternalIdentityEntity {
+    ExternalIdentityEntity {
+        id: None,
+        user_id,
+        provider: provider.to_string(),
+        subject: account.subject.clone(),
+        email: account.email.clone(),
+        access_token: account.access_token.clone(),
+    }
+}
+
+/// whether the user can still log in once one of its linked accounts is gone
+fn keeps_a_sign_in(user: &UserEntity, linked: usize) -> bool {
+    user.verifier.is_some() || linked > 1
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_last_sign_in_stays_linked() {
+        let mut user = UserEntity::from_external_account(&ExternalAccount::new());
+        assert!(!keeps_a_sign_in(&user, 1));
+        assert!(keeps_a_sign_in(&user, 2));
+        user.verifier = Some("1f3b".to_string());
+        assert!(keeps_a_sign_in(&user, 1));
+    }
+}
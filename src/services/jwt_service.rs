@@ -1,20 +1,24 @@
 use chrono::{DateTime, NaiveDateTime, Utc};
-use jsonwebtoken::{Algorithm, decode, DecodingKey, encode, EncodingKey, Header, TokenData, Validation};
+use jsonwebtoken::{decode, decode_header, encode, Header, Validation};
 use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter, Error};
 use std::str::FromStr;
 
+use crate::services::key_manager::KeyManager;
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+/// SERVICE sessions belong to a registered client, their sub is the client id and not a user
 pub enum SessionType {
-    USER, GUEST, SYSADMIN
+    USER, GUEST, SYSADMIN, SERVICE
 }
 
 impl Display for SessionType {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            SessionType::USER => {write!(f,"{}", "USER".to_string())}
-            SessionType::GUEST => {write!(f,"{}", "GUEST".to_string())}
-            SessionType::SYSADMIN => {write!(f,"{}", "SYSADMIN".to_string())}
+            SessionType::USER => {write!(f, "USER")}
+            SessionType::GUEST => {write!(f, "GUEST")}
+            SessionType::SYSADMIN => {write!(f, "SYSADMIN")}
+            SessionType::SERVICE => {write!(f, "SERVICE")}
         }
     }
 }
@@ -27,6 +31,39 @@ impl FromStr for SessionType {
             "USER" => {Ok(SessionType::USER)}
             "GUEST" => {Ok(SessionType::GUEST)},
             "SYSADMIN" => {Ok(SessionType::SYSADMIN)},
+            "SERVICE" => {Ok(SessionType::SERVICE)},
+            &_ => {Err(Error)}
+        }
+    }
+}
+
+/// names of the rows of the permission table, spelled as stored
+#[allow(non_camel_case_types)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub enum Permission {
+    GLOBAL_CREATE, GLOBAL_READ, GLOBAL_UPDATE, GLOBAL_DELETE
+}
+
+impl Display for Permission {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Permission::GLOBAL_CREATE => {write!(f, "GLOBAL_CREATE")}
+            Permission::GLOBAL_READ => {write!(f, "GLOBAL_READ")}
+            Permission::GLOBAL_UPDATE => {write!(f, "GLOBAL_UPDATE")}
+            Permission::GLOBAL_DELETE => {write!(f, "GLOBAL_DELETE")}
+        }
+    }
+}
+
+impl FromStr for Permission {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "GLOBAL_CREATE" => {Ok(Permission::GLOBAL_CREATE)}
+            "GLOBAL_READ" => {Ok(Permission::GLOBAL_READ)},
+            "GLOBAL_UPDATE" => {Ok(Permission::GLOBAL_UPDATE)},
+            "GLOBAL_DELETE" => {Ok(Permission::GLOBAL_DELETE)},
             &_ => {Err(Error)}
         }
     }
@@ -37,27 +74,79 @@ pub enum AuthenticationProvider {
     FACEBOOK, GOOGLE, TWITTER, MANUAL, APPLE, GUEST
 }
 
+/// the provider names of the social login routes, /iot/auth2/apple/login1
+impl Display for AuthenticationProvider {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuthenticationProvider::FACEBOOK => {write!(f, "facebook")}
+            AuthenticationProvider::GOOGLE => {write!(f, "google")}
+            AuthenticationProvider::TWITTER => {write!(f, "twitter")}
+            AuthenticationProvider::MANUAL => {write!(f, "manual")}
+            AuthenticationProvider::APPLE => {write!(f, "apple")}
+            AuthenticationProvider::GUEST => {write!(f, "guest")}
+        }
+    }
+}
 
+
+/// claims of access tokens and, with session_type left empty, of OIDC id_tokens
 #[derive(Debug, Serialize, Deserialize)]
 pub struct JwtClaims {
+    /// registered as jti, tokens issued before the rename carry it as jwt_id
+    #[serde(rename = "jti", alias = "jwt_id")]
     pub jwt_id: Option<String>,
     pub sub: Option<String>,
     pub aud: Option<String>,
-    pub issuer: Option<String>,
+    pub iss: Option<String>,
     pub session_type: Option<SessionType>,
     pub access_token: Option<String>,
+    /// echoed from the authentication request into the id_token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_time: Option<usize>,
+    /// granted to the user through user_permission when the token was issued
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub permissions: Vec<Permission>,
+    /// space delimited OAuth2 scopes granted at issuance
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
     pub iat: usize,
+    /// iat in milliseconds, orders the token against a subject revocation made in the same second
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iat_ms: Option<i64>,
     pub exp: usize
 
 }
 
-pub fn issue(claims: &mut JwtClaims) -> String {
-    let header = Header::new(Algorithm::HS256);
-    encode(&header, claims, &EncodingKey::from_secret("secret".as_ref())).unwrap()
+impl JwtClaims {
+    /// tokens issued before iat_ms was added count from the start of their second
+    pub fn issued_at_ms(&self) -> i64 {
+        self.iat_ms.unwrap_or(self.iat as i64 * 1000)
+    }
+
+    pub fn scopes(&self) -> Vec<String> {
+        self.scope.as_ref()
+            .map_or(vec![], |scope| scope.split_whitespace().map(|s| s.to_string()).collect())
+    }
 }
 
-pub fn verify(token: &String) -> Option<JwtClaims> {
-    let result = decode::<JwtClaims>(token, &DecodingKey::from_secret("secret".as_ref()), &Validation::default());
+/// sign with the active key, its kid goes into the header
+pub fn issue(keys: &KeyManager, claims: &mut JwtClaims) -> String {
+    let (key, encoding_key) = keys.signing_key();
+    let mut header = Header::new(key.algorithm);
+    header.kid = Some(key.kid.clone());
+    encode(&header, claims, encoding_key).unwrap()
+}
+
+/// verify with the key named by the header kid, only that key's algorithm is accepted
+pub fn verify(keys: &KeyManager, token: &String) -> Option<JwtClaims> {
+    let header = match decode_header(token) {
+        Ok(header) => header,
+        Err(_) => return None
+    };
+    let key = keys.verification_key(header.kid.as_deref())?;
+    let result = decode::<JwtClaims>(token, key.decoding_key(), &Validation::new(key.algorithm));
     match result {
         Ok(data) => {Some(data.claims)}
         Err(_) => {None}
@@ -72,6 +161,7 @@ mod test {
     use chrono::{Duration, NaiveDateTime, Timelike, Utc};
 
     use super::*;
+    use crate::services::key_manager::{test_claims, test_key_manager};
 
     #[test]
     fn test_time() {
@@ -88,23 +178,39 @@ mod test {
         let mut claims = JwtClaims {
             aud: Some("".to_string()),
             exp: Utc::now().add(Duration::days(1000)).timestamp() as usize,
-            iat: Utc::now().timestamp() as usize,
-            issuer: Some("infotamia".to_string()),
+            iss: Some("infotamia".to_string()),
             jwt_id: Some("myid".to_string()),
             sub: Some("mohammedalanny@gmail.com".to_string()),
             access_token: Some("hdhsjhdjshdjsk".to_string()),
-            session_type: Some(SessionType::USER)
+            ..test_claims()
         };
 
-        let token = issue(&mut claims);
+        let keys = test_key_manager();
+        let token = issue(&keys, &mut claims);
         print!("jwt = {}", token.clone());
         assert!(!token.is_empty());
 
         // verify it is correct
-        let verified_claims = verify(&token).unwrap();
+        let verified_claims = verify(&keys, &token).unwrap();
         assert_eq!(claims.sub.unwrap(), verified_claims.sub.unwrap());
         assert_eq!(claims.jwt_id.unwrap(), verified_claims.jwt_id.unwrap());
-        assert_eq!(claims.issuer.unwrap(), verified_claims.issuer.unwrap());
+        assert_eq!(claims.iss.unwrap(), verified_claims.iss.unwrap());
         assert_eq!(verified_claims.session_type.unwrap(), SessionType::USER);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_jti_claim_name() {
+        let claims: JwtClaims = serde_json::from_str(r#"{"jwt_id":"myid","sub":null,"aud":null,"iss":null,"session_type":null,"access_token":null,"iat":0,"exp":0}"#).unwrap();
+        assert_eq!(claims.jwt_id.as_deref(), Some("myid"));
+        let payload = serde_json::to_string(&claims).unwrap();
+        assert!(payload.contains(r#""jti":"myid""#));
+        assert!(!payload.contains("jwt_id"));
+    }
+
+    #[test]
+    fn test_session_type_names() {
+        for session_type in [SessionType::USER, SessionType::GUEST, SessionType::SYSADMIN, SessionType::SERVICE] {
+            assert_eq!(session_type.to_string().parse::<SessionType>().unwrap(), session_type);
+        }
+    }
+}
@@ -0,0 +1,386 @@
+use std::collections::HashMap;
+use std::{fs, process};
+
+use chrono::Utc;
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey};
+use openssl::bn::{BigNum, BigNumContext};
+use openssl::pkey::PKey;
+use serde::Deserialize;
+
+use crate::entities::jwk_entities::Jwk;
+#[cfg(test)]
+use crate::services::jwt_service::{issue, JwtClaims, SessionType};
+
+#[derive(Deserialize, Debug)]
+pub struct JwtConfiguration {
+    /// kid of the key new tokens are signed with
+    pub active_kid: String,
+    pub keys: Vec<JwtKeyConfiguration>,
+}
+
+/// one signing key, HS* keys carry a secret, RS* and ES* keys point to PEM files.
+/// every key but the active one is retiring, it only verifies tokens until verify_until
+#[derive(Deserialize, Debug)]
+pub struct JwtKeyConfiguration {
+    pub kid: String,
+    pub algorithm: Algorithm,
+    pub secret: Option<String>,
+    pub private_key_path: Option<String>,
+    pub public_key_path: Option<String>,
+    pub verify_until: Option<i64>,
+}
+
+pub struct ManagedKey {
+    pub kid: String,
+    pub algorithm: Algorithm,
+    pub verify_until: Option<i64>,
+    /// PEM of the public half, None for HMAC keys
+    pub public_key_pem: Option<Vec<u8>>,
+    encoding_key: Option<EncodingKey>,
+    decoding_key: DecodingKey<'static>,
+}
+
+impl ManagedKey {
+    pub fn decoding_key(&self) -> &DecodingKey<'static> {
+        &self.decoding_key
+    }
+
+    /// a retiring key whose rotation window has passed no longer verifies anything
+    pub fn is_expired(&self) -> bool {
+        self.verify_until.is_some_and(|until| until <= Utc::now().timestamp())
+    }
+
+    pub fn is_symmetric(&self) -> bool {
+        self.public_key_pem.is_none()
+    }
+
+    /// the public key as a JWK, None for HMAC keys which must never be published
+    pub fn jwk(&self) -> Option<Jwk> {
+        let pem = self.public_key_pem.as_ref()?;
+        let key = PKey::public_key_from_pem(pem).ok()?;
+        let encode = |bytes: Vec<u8>| base64::encode_config(bytes, base64::URL_SAFE_NO_PAD);
+        let mut jwk = Jwk {
+            kty: String::new(),
+            kid: self.kid.clone(),
+            alg: format!("{:?}", self.algorithm),
+            key_use: "sig".to_string(),
+            n: None,
+            e: None,
+            crv: None,
+            x: None,
+            y: None,
+        };
+        match self.algorithm {
+            Algorithm::ES256 | Algorithm::ES384 => {
+                let (crv, size) = match self.algorithm {
+                    Algorithm::ES256 => ("P-256", 32),
+                    _ => ("P-384", 48)
+                };
+                let ec = key.ec_key().ok()?;
+                let mut ctx = BigNumContext::new().ok()?;
+                let mut x = BigNum::new().ok()?;
+                let mut y = BigNum::new().ok()?;
+                ec.public_key().affine_coordinates_gfp(ec.group(), &mut x, &mut y, &mut ctx).ok()?;
+                jwk.kty = "EC".to_string();
+                jwk.crv = Some(crv.to_string());
+                jwk.x = Some(encode(left_pad(x.to_vec(), size)));
+                jwk.y = Some(encode(left_pad(y.to_vec(), size)));
+            }
+            _ => {
+                let rsa = key.rsa().ok()?;
+                jwk.kty = "RSA".to_string();
+                jwk.n = Some(encode(rsa.n().to_vec()));
+                jwk.e = Some(encode(rsa.e().to_vec()));
+            }
+        }
+        Some(jwk)
+    }
+}
+
+/// signing keys of the service, the active key signs and every known key verifies by kid
+pub struct KeyManager {
+    active_kid: String,
+    keys: HashMap<String, ManagedKey>,
+}
+
+impl KeyManager {
+    pub fn new() -> Self {
+        let jwt_config = fs::read_to_string("./jwt_configuration.json").unwrap_or_else(|err| {
+            eprintln!("error reading file {}", err);
+            process::exit(1);
+        });
+
+        let config: JwtConfiguration = serde_json::from_str(jwt_config.as_str()).unwrap_or_else(|err| {
+            eprintln!("error deserializing file content {}", err);
+            process::exit(1);
+        });
+
+        KeyManager::from_configuration(&config).unwrap_or_else(|err| {
+            eprintln!("error loading jwt keys {}", err);
+            process::exit(1);
+        })
+    }
+
+    pub fn from_configuration(config: &JwtConfiguration) -> Result<Self, String> {
+        let mut keys = HashMap::new();
+        for key_config in &config.keys {
+            let key = load_key(key_config)?;
+            keys.insert(key.kid.clone(), key);
+        }
+        match keys.get(&config.active_kid) {
+            Some(key) if key.encoding_key.is_some() => {}
+            Some(_) => return Err(format!("active key {} cannot sign", config.active_kid)),
+            None => return Err(format!("active key {} is not configured", config.active_kid))
+        }
+        Ok(KeyManager {
+            active_kid: config.active_kid.clone(),
+            keys,
+        })
+    }
+
+    /// the active key, new tokens are signed with it
+    pub fn signing_key(&self) -> (&ManagedKey, &EncodingKey) {
+        let key = &self.keys[&self.active_kid];
+        (key, key.encoding_key.as_ref().unwrap())
+    }
+
+    /// key for a token header kid, tokens without a kid are checked against the active key
+    pub fn verification_key(&self, kid: Option<&str>) -> Option<&ManagedKey> {
+        let kid = kid.unwrap_or(self.active_kid.as_str());
+        self.keys.get(kid).filter(|key| !key.is_expired())
+    }
+
+    /// every key that still verifies tokens
+    pub fn verification_keys(&self) -> Vec<&ManagedKey> {
+        let mut keys: Vec<&ManagedKey> = self.keys.values().filter(|key| !key.is_expired()).collect();
+        keys.sort_by(|a, b| a.kid.cmp(&b.kid));
+        keys
+    }
+}
+
+/// EC coordinates are fixed length in a JWK, leading zero bytes included
+fn left_pad(bytes: Vec<u8>, size: usize) -> Vec<u8> {
+    let mut padded = vec![0u8; size.saturating_sub(bytes.len())];
+    padded.extend_from_slice(&bytes);
+    padded
+}
+
+fn load_key(config: &JwtKeyConfiguration) -> Result<ManagedKey, String> {
+    let read = |path: &String| fs::read(path).map_err(|err| format!("error reading {} {}", path, err));
+    let invalid = |err: jsonwebtoken::errors::Error| format!("invalid key {} {}", config.kid, err);
+    let (encoding_key, decoding_key, public_key_pem) = match config.algorithm {
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => {
+            let secret = config.secret.as_ref().ok_or(format!("key {} has no secret", config.kid))?;
+            (Some(EncodingKey::from_secret(secret.as_bytes())), DecodingKey::from_secret(secret.as_bytes()).into_static(), None)
+        }
+        Algorithm::ES256 | Algorithm::ES384 => {
+            let public_pem = read(config.public_key_path.as_ref().ok_or(format!("key {} has no public key", config.kid))?)?;
+            let encoding_key = match config.private_key_path {
+                None => None,
+                Some(ref path) => Some(EncodingKey::from_ec_pem(&read(path)?).map_err(invalid)?)
+            };
+            let decoding_key = DecodingKey::from_ec_pem(&public_pem).map_err(invalid)?.into_static();
+            (encoding_key, decoding_key, Some(public_pem))
+        }
+        _ => {
+            let public_pem = read(config.public_key_path.as_ref().ok_or(format!("key {} has no public key", config.kid))?)?;
+            let encoding_key = match config.private_key_path {
+                None => None,
+                Some(ref path) => Some(EncodingKey::from_rsa_pem(&read(path)?).map_err(invalid)?)
+            };
+            let decoding_key = DecodingKey::from_rsa_pem(&public_pem).map_err(invalid)?.into_static();
+            (encoding_key, decoding_key, Some(public_pem))
+        }
+    };
+    Ok(ManagedKey {
+        kid: config.kid.clone(),
+        algorithm: config.algorithm,
+        verify_until: config.verify_until,
+        public_key_pem,
+        encoding_key,
+        decoding_key,
+    })
+}
+
+/// HS256 key manager for tests
+#[cfg(test)]
+pub fn test_key_manager() -> KeyManager {
+    KeyManager::from_configuration(&JwtConfiguration {
+        active_kid: "test".to_string(),
+        keys: vec![JwtKeyConfiguration {
+            kid: "test".to_string(),
+            algorithm: Algorithm::HS256,
+            secret: Some("secret".to_string()),
+            private_key_path: None,
+            public_key_path: None,
+            verify_until: None,
+        }],
+    }).unwrap()
+}
+
+/// a five minute USER session of moe@gmail.com, tests override what they need with `..test_claims()`
+#[cfg(test)]
+pub fn test_claims() -> JwtClaims {
+    let now = Utc::now().timestamp() as usize;
+    JwtClaims {
+        jwt_id: Some(uuid::Uuid::new_v4().to_string()),
+        sub: Some("moe@gmail.com".to_string()),
+        aud: None,
+        iss: None,
+        session_type: Some(SessionType::USER),
+        access_token: None,
+        nonce: None,
+        auth_time: None,
+        permissions: vec![],
+        scope: None,
+        iat: now,
+        // None so tests moving iat move the issuance with it
+        iat_ms: None,
+        exp: now + 300,
+    }
+}
+
+/// the claims signed by test_key_manager()
+#[cfg(test)]
+pub fn test_token(mut claims: JwtClaims) -> String {
+    issue(&test_key_manager(), &mut claims)
+}
+
+#[cfg(test)]
+mod test {
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::nid::Nid;
+    use openssl::rsa::Rsa;
+    use jsonwebtoken::decode_header;
+
+    use super::*;
+    use crate::services::jwt_service::verify;
+
+    /// write a fresh key pair to the temp dir, returns (private, public) paths
+    fn write_key_pair(name: &str, algorithm: Algorithm) -> (String, String) {
+        let key = match algorithm {
+            Algorithm::ES256 => {
+                let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+                PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap()
+            }
+            _ => PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap()
+        };
+        let dir = std::env::temp_dir();
+        let private_path = dir.join(format!("{}-{}.pem", name, uuid::Uuid::new_v4())).to_str().unwrap().to_string();
+        let public_path = dir.join(format!("{}-{}.pub.pem", name, uuid::Uuid::new_v4())).to_str().unwrap().to_string();
+        fs::write(&private_path, key.private_key_to_pem_pkcs8().unwrap()).unwrap();
+        fs::write(&public_path, key.public_key_to_pem().unwrap()).unwrap();
+        (private_path, public_path)
+    }
+
+    fn key_config(kid: &str, algorithm: Algorithm, verify_until: Option<i64>) -> JwtKeyConfiguration {
+        let (private_key_path, public_key_path) = match algorithm {
+            Algorithm::HS256 => (None, None),
+            _ => {
+                let (private_path, public_path) = write_key_pair(kid, algorithm);
+                (Some(private_path), Some(public_path))
+            }
+        };
+        JwtKeyConfiguration {
+            kid: kid.to_string(),
+            algorithm,
+            secret: Some(format!("{}-secret", kid)),
+            private_key_path,
+            public_key_path,
+            verify_until,
+        }
+    }
+
+    #[test]
+    fn test_load_keys() {
+        let config = JwtConfiguration {
+            active_kid: "rs".to_string(),
+            keys: vec![
+                key_config("rs", Algorithm::RS256, None),
+                key_config("es", Algorithm::ES256, None),
+                key_config("hs", Algorithm::HS256, Some(Utc::now().timestamp() + 60)),
+                key_config("old", Algorithm::HS256, Some(Utc::now().timestamp() - 60)),
+            ],
+        };
+        let keys = KeyManager::from_configuration(&config).unwrap();
+        assert_eq!(keys.signing_key().0.kid, "rs");
+        assert!(keys.verification_key(Some("es")).is_some());
+        assert!(keys.verification_key(Some("hs")).is_some());
+        // past its rotation window
+        assert!(keys.verification_key(Some("old")).is_none());
+        assert!(keys.verification_key(Some("unknown")).is_none());
+        assert_eq!(keys.verification_keys().len(), 3);
+    }
+
+    #[test]
+    fn test_jwk() {
+        let config = JwtConfiguration {
+            active_kid: "rs".to_string(),
+            keys: vec![
+                key_config("rs", Algorithm::RS256, None),
+                key_config("es", Algorithm::ES256, None),
+                key_config("hs", Algorithm::HS256, None),
+            ],
+        };
+        let keys = KeyManager::from_configuration(&config).unwrap();
+        let rs = keys.verification_key(Some("rs")).unwrap().jwk().unwrap();
+        assert_eq!((rs.kty.as_str(), rs.alg.as_str(), rs.key_use.as_str()), ("RSA", "RS256", "sig"));
+        assert_eq!(rs.e.unwrap(), "AQAB");
+        let es = keys.verification_key(Some("es")).unwrap().jwk().unwrap();
+        assert_eq!((es.kty.as_str(), es.crv.unwrap().as_str()), ("EC", "P-256"));
+        assert_eq!(base64::decode_config(es.x.unwrap(), base64::URL_SAFE_NO_PAD).unwrap().len(), 32);
+        assert!(keys.verification_key(Some("hs")).unwrap().jwk().is_none());
+    }
+
+    #[test]
+    fn test_active_key_must_sign() {
+        let mut public_only = key_config("rs", Algorithm::RS256, None);
+        public_only.private_key_path = None;
+        let config = JwtConfiguration {
+            active_kid: "rs".to_string(),
+            keys: vec![public_only],
+        };
+        assert!(KeyManager::from_configuration(&config).is_err());
+    }
+
+    #[test]
+    fn test_rotation_window() {
+        let old_key = key_config("2021-01", Algorithm::RS256, None);
+        let new_key = key_config("2021-02", Algorithm::ES256, None);
+        let old_config = JwtConfiguration {
+            active_kid: "2021-01".to_string(),
+            keys: vec![key_config_from(&old_key, None)],
+        };
+        let mut claims = test_claims();
+        let token = issue(&KeyManager::from_configuration(&old_config).unwrap(), &mut claims);
+        assert_eq!(decode_header(&token).unwrap().kid.unwrap(), "2021-01");
+
+        // rotated, the old key still verifies during its window
+        let rotated = KeyManager::from_configuration(&JwtConfiguration {
+            active_kid: "2021-02".to_string(),
+            keys: vec![key_config_from(&new_key, None), key_config_from(&old_key, Some(Utc::now().timestamp() + 60))],
+        }).unwrap();
+        assert!(verify(&rotated, &token).is_some());
+        let new_token = issue(&rotated, &mut claims);
+        assert_eq!(decode_header(&new_token).unwrap().alg, Algorithm::ES256);
+        assert!(verify(&rotated, &new_token).is_some());
+
+        // and stops once the window is over
+        let retired = KeyManager::from_configuration(&JwtConfiguration {
+            active_kid: "2021-02".to_string(),
+            keys: vec![key_config_from(&new_key, None), key_config_from(&old_key, Some(Utc::now().timestamp() - 1))],
+        }).unwrap();
+        assert!(verify(&retired, &token).is_none());
+    }
+
+    fn key_config_from(config: &JwtKeyConfiguration, verify_until: Option<i64>) -> JwtKeyConfiguration {
+        JwtKeyConfiguration {
+            kid: config.kid.clone(),
+            algorithm: config.algorithm,
+            secret: config.secret.clone(),
+            private_key_path: config.private_key_path.clone(),
+            public_key_path: config.public_key_path.clone(),
+            verify_until,
+        }
+    }
+}
\ No newline at end of file
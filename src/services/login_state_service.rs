@@ -0,0 +1,103 @@
+use chrono::{Duration, Utc};
+use log::warn;
+use sqlx::MySqlPool;
+
+use crate::daos::login_state_dao::LoginStateDao;
+use crate::entities::login_state_entity::LoginStateEntity;
+use crate::ouath::oauth::LoginState;
+use crate::services::token_service::{consume_once, generate_token, hash_token};
+
+/// a stored social login, the state and PKCE verifier to send to the provider
+pub struct StartedLogin {
+    pub state: String,
+    pub code_verifier: String,
+}
+
+pub struct LoginStateService<'a> {
+    login_state_dao: LoginStateDao<'a>
+}
+
+impl <'a> LoginStateService<'a> {
+    pub fn new(conn: &'a MySqlPool) -> Self {
+        LoginStateService {
+            login_state_dao: LoginStateDao::new(conn)
+        }
+    }
+
+    /// store a login to the provider for the browser holding the binding, only the hash of the state is kept
+    pub async fn start(&mut self, provider: &str, browser_binding: &String, login_state: LoginState, ttl: Duration) -> Option<StartedLogin> {
+        let state = generate_token();
+        let code_verifier = generate_token();
+        let entity = LoginStateEntity {
+            id: None,
+            state_hash: hash_token(&state),
+            browser_hash: hash_token(browser_binding),
+            provider: provider.to_string(),
+            code_verifier: code_verifier.clone(),
+            nonce: login_state.nonce,
+            scope: login_state.scope,
+            pending_authorization: login_state.authorization,
+            link_user_id: login_state.link_user_id,
+            expires_at: Utc::now().timestamp() + ttl.num_seconds(),
+            used_at: None,
+        };
+        if self.login_state_dao.insert_one(&entity).await {
+            Some(StartedLogin { state, code_verifier })
+        } else {
+            None
+        }
+    }
+
+    /// use up the login of a callback, None unless it is live, was started for the provider
+    /// and by the same browser. a callback failing the checks still uses the state up
+    pub async fn consume(&mut self, state: &String, provider: &str, browser_binding: Option<&String>) -> Option<LoginStateEntity> {
+        let now = Utc::now().timestamp();
+        let entity = self.login_state_dao.find_by_state_hash(&hash_token(state)).await?;
+        if !consume_once(entity.used_at, self.login_state_dao.mark_used(entity.id?, now)).await {
+            warn!("{} login state presented twice", entity.provider);
+            return None;
+        }
+        if accepts(&entity, provider, browser_binding, now) {
+            Some(entity)
+        } else {
+            warn!("{} login state presented by another browser, provider or too late", entity.provider);
+            None
+        }
+    }
+}
+
+fn accepts(entity: &LoginStateEntity, provider: &str, browser_binding: Option<&String>, now: i64) -> bool {
+    let same_browser = browser_binding.is_some_and(|binding| {
+        openssl::memcmp::eq(hash_token(binding).as_bytes(), entity.browser_hash.as_bytes())
+    });
+    same_browser && entity.provider == provider && entity.expires_at > now
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_accepts_only_the_starting_browser() {
+        let binding = generate_token();
+        let now = Utc::now().timestamp();
+        let entity = LoginStateEntity {
+            id: Some(1),
+            state_hash: hash_token(&generate_token()),
+            browser_hash: hash_token(&binding),
+            provider: "google".to_string(),
+            code_verifier: generate_token(),
+            nonce: None,
+            scope: "openid".to_string(),
+            pending_authorization: None,
+            link_user_id: None,
+            expires_at: now + 60,
+            used_at: None,
+        };
+        assert!(accepts(&entity, "google", Some(&binding), now));
+        assert!(!accepts(&entity, "google", Some(&generate_token()), now));
+        assert!(!accepts(&entity, "google", None, now));
+        assert!(!accepts(&entity, "twitter", Some(&binding), now));
+        assert!(!accepts(&entity, "google", Some(&binding), now + 60));
+    }
+}
@@ -1,2 +1,14 @@
 pub mod jwt_service;
 pub mod user_service;
+pub mod srp_service;
+pub mod srp_session_store;
+pub mod revocation_store;
+pub mod key_manager;
+pub mod oidc_service;
+pub mod token_service;
+pub mod client_service;
+pub mod authorization_code_service;
+pub mod device_authorization_service;
+pub mod login_state_service;
+pub mod external_identity_service;
+pub mod cleanup_service;
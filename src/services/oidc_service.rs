@@ -0,0 +1,269 @@
+use std::{fs, process};
+use std::ops::Add;
+
+use chrono::{Duration, Utc};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::entities::oidc_entities::OidcDiscoveryDocument;
+use crate::exceptions::error_base::{ErrorResponse, HttpErrorCode};
+use crate::services::jwt_service::{issue, JwtClaims, Permission, SessionType};
+use crate::services::key_manager::KeyManager;
+
+#[derive(Deserialize, Debug)]
+pub struct OidcConfiguration {
+    /// public base url of the service, the iss of every token we sign
+    pub issuer: String,
+    /// client id relying parties validate the id_token aud against
+    pub audience: String,
+    #[serde(default = "default_id_token_ttl_seconds")]
+    pub id_token_ttl_seconds: i64,
+    /// access tokens are short lived, clients renew them with a refresh token
+    #[serde(default = "default_access_token_ttl_seconds")]
+    pub access_token_ttl_seconds: i64,
+    #[serde(default = "default_refresh_token_ttl_seconds")]
+    pub refresh_token_ttl_seconds: i64,
+    /// how often revocations made on other instances are reloaded from the shared tables
+    #[serde(default = "default_revocation_sync_interval_seconds")]
+    pub revocation_sync_interval_seconds: u64,
+    /// how often expired codes, login states, password resets and refresh tokens are deleted
+    #[serde(default = "default_cleanup_interval_seconds")]
+    pub cleanup_interval_seconds: u64,
+    /// authorization codes are exchanged right after the redirect
+    #[serde(default = "default_authorization_code_ttl_seconds")]
+    pub authorization_code_ttl_seconds: i64,
+    /// how long a device waits for its user to approve it
+    #[serde(default = "default_device_code_ttl_seconds")]
+    pub device_code_ttl_seconds: i64,
+    /// page of our app where users sign in and type the user code shown on a device,
+    /// the app answers it with POST /oauth/device
+    pub device_verification_uri: String,
+    /// seconds a device waits between polls of the token endpoint
+    #[serde(default = "default_device_poll_interval_seconds")]
+    pub device_poll_interval_seconds: i64,
+    /// how long a social login may take between login1 and the provider's callback
+    #[serde(default = "default_login_state_ttl_seconds")]
+    pub login_state_ttl_seconds: i64,
+    /// every scope a token may carry, logins that ask for none are granted all of them
+    #[serde(default = "default_scopes_supported")]
+    pub scopes_supported: Vec<String>,
+}
+
+fn default_id_token_ttl_seconds() -> i64 { 3600 }
+fn default_access_token_ttl_seconds() -> i64 { 900 }
+fn default_refresh_token_ttl_seconds() -> i64 { 30 * 24 * 3600 }
+fn default_revocation_sync_interval_seconds() -> u64 { 30 }
+fn default_cleanup_interval_seconds() -> u64 { 3600 }
+fn default_authorization_code_ttl_seconds() -> i64 { 60 }
+fn default_device_code_ttl_seconds() -> i64 { 600 }
+fn default_device_poll_interval_seconds() -> i64 { 5 }
+fn default_login_state_ttl_seconds() -> i64 { 600 }
+fn default_scopes_supported() -> Vec<String> { vec!["openid".to_string(), "email".to_string(), "profile".to_string()] }
+
+impl OidcConfiguration {
+    pub fn new() -> Self {
+        let oidc_config = fs::read_to_string("./oidc_configuration.json").unwrap_or_else(|err| {
+            eprintln!("error reading file {}", err);
+            process::exit(1);
+        });
+
+        serde_json::from_str(oidc_config.as_str()).unwrap_or_else(|err| {
+            eprintln!("error deserializing file content {}", err);
+            process::exit(1);
+        })
+    }
+
+    /// no token we sign outlives this, nor does a refresh token between two rotations
+    pub fn max_token_lifetime_seconds(&self) -> i64 {
+        self.id_token_ttl_seconds.max(self.access_token_ttl_seconds).max(self.refresh_token_ttl_seconds)
+    }
+
+    /// scope granted for a login asking for the requested one, None when it names an unsupported scope
+    pub fn grant_scope(&self, requested: Option<&str>) -> Option<String> {
+        match requested {
+            None => Some(self.scopes_supported.join(" ")),
+            Some(requested) => narrow_scope(&self.scopes_supported.join(" "), Some(requested))
+        }
+    }
+
+    /// bearer token of a session, access_token is the provider token of social logins
+    pub fn access_token(&self, keys: &KeyManager, subject: &str, session_type: SessionType, access_token: Option<String>, permissions: Vec<Permission>, scope: String) -> String {
+        let now = Utc::now();
+        let mut claims = JwtClaims {
+            aud: None,
+            exp: now.add(Duration::seconds(self.access_token_ttl_seconds)).timestamp() as usize,
+            iat: now.timestamp() as usize,
+            iat_ms: Some(now.timestamp_millis()),
+            iss: Some(self.issuer.clone()),
+            jwt_id: Some(Uuid::new_v4().to_string()),
+            sub: Some(subject.to_string()),
+            access_token,
+            nonce: None,
+            auth_time: None,
+            permissions,
+            scope: Some(scope),
+            session_type: Some(session_type),
+        };
+        issue(keys, &mut claims)
+    }
+
+    /// id_token issued next to the access token of a fresh login, audience is the client it is for
+    pub fn id_token(&self, keys: &KeyManager, audience: &str, subject: &str, nonce: Option<String>, auth_time: usize) -> String {
+        let now = Utc::now();
+        let mut claims = JwtClaims {
+            aud: Some(audience.to_string()),
+            exp: now.add(Duration::seconds(self.id_token_ttl_seconds)).timestamp() as usize,
+            iat: now.timestamp() as usize,
+            iat_ms: Some(now.timestamp_millis()),
+            iss: Some(self.issuer.clone()),
+            jwt_id: Some(Uuid::new_v4().to_string()),
+            sub: Some(subject.to_string()),
+            access_token: None,
+            nonce,
+            auth_time: Some(auth_time),
+            permissions: vec![],
+            scope: None,
+            // no session type, an id_token is never accepted as a bearer token
+            session_type: None,
+        };
+        issue(keys, &mut claims)
+    }
+
+    pub fn discovery_document(&self, keys: &KeyManager) -> OidcDiscoveryDocument {
+        let issuer = self.issuer.trim_end_matches('/');
+        let mut algorithms: Vec<String> = keys.verification_keys().iter()
+            .map(|key| format!("{:?}", key.algorithm))
+            .collect();
+        algorithms.sort();
+        algorithms.dedup();
+        let strings = |values: &[&str]| values.iter().map(|value| value.to_string()).collect();
+        OidcDiscoveryDocument {
+            issuer: self.issuer.clone(),
+            authorization_endpoint: format!("{}/oauth/authorize", issuer),
+            token_endpoint: format!("{}/oauth/token", issuer),
+            device_authorization_endpoint: format!("{}/oauth/device_authorization", issuer),
+            userinfo_endpoint: format!("{}/oauth/userinfo", issuer),
+            jwks_uri: format!("{}/.well-known/jwks.json", issuer),
+            response_types_supported: strings(&["code"]),
+            grant_types_supported: strings(&["authorization_code", "client_credentials", "refresh_token", DEVICE_CODE_GRANT_TYPE]),
+            code_challenge_methods_supported: strings(&["S256"]),
+            token_endpoint_auth_methods_supported: strings(&["client_secret_basic", "none"]),
+            subject_types_supported: strings(&["public"]),
+            id_token_signing_alg_values_supported: algorithms,
+            scopes_supported: self.scopes_supported.clone(),
+            claims_supported: strings(&["iss", "sub", "aud", "exp", "iat", "auth_time", "nonce", "email", "given_name", "family_name"]),
+        }
+    }
+}
+
+/// RFC 8628 3.4 grant_type of a device polling the token endpoint
+pub const DEVICE_CODE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// the requested scopes when every one of them is granted, the granted ones when none are requested.
+/// a token can only be exchanged for a narrower one
+pub fn narrow_scope(granted: &str, requested: Option<&str>) -> Option<String> {
+    let granted: Vec<&str> = granted.split_whitespace().collect();
+    let requested = match requested {
+        None => return Some(granted.join(" ")),
+        Some(requested) => requested
+    };
+    let mut scopes: Vec<&str> = vec![];
+    for scope in requested.split_whitespace() {
+        if !granted.contains(&scope) {
+            return None;
+        }
+        if !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+    }
+    if scopes.is_empty() {
+        return None;
+    }
+    Some(scopes.join(" "))
+}
+
+/// the scope a client gets of what was granted: narrow_scope of the scopes the client is allowed as well,
+/// None when the two have nothing in common
+pub fn client_scope(granted: &str, allowed_scopes: &str, requested: Option<&str>) -> Option<String> {
+    let allowed: Vec<&str> = allowed_scopes.split_whitespace().collect();
+    let common: Vec<&str> = granted.split_whitespace().filter(|scope| allowed.contains(scope)).collect();
+    narrow_scope(&common.join(" "), requested).filter(|scope| !scope.is_empty())
+}
+
+/// RFC 6749 answer to a request for scopes that cannot be granted
+pub fn invalid_scope() -> HttpErrorCode {
+    HttpErrorCode::BadRequest {message : ErrorResponse {message: "requested scope not granted".to_string(), error_code : "invalid_scope".to_string()}}
+}
+
+/// configuration for tests
+#[cfg(test)]
+pub fn test_oidc_configuration() -> OidcConfiguration {
+    OidcConfiguration {
+        issuer: "https://infotamia.com".to_string(),
+        audience: "infotamia".to_string(),
+        id_token_ttl_seconds: 3600,
+        access_token_ttl_seconds: 900,
+        refresh_token_ttl_seconds: 3600,
+        revocation_sync_interval_seconds: 30,
+        cleanup_interval_seconds: 3600,
+        authorization_code_ttl_seconds: 60,
+        device_code_ttl_seconds: 600,
+        device_verification_uri: "https://app.infotamia.com/device".to_string(),
+        device_poll_interval_seconds: 5,
+        login_state_ttl_seconds: 600,
+        scopes_supported: vec!["openid".to_string(), "email".to_string(), "devices:read".to_string(), "devices:write".to_string()],
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::services::jwt_service::verify;
+    use crate::services::key_manager::test_key_manager;
+
+    use super::*;
+
+    #[test]
+    fn test_id_token_claims() {
+        let config = test_oidc_configuration();
+        let keys = test_key_manager();
+        let auth_time = Utc::now().timestamp() as usize;
+        let id_token = config.id_token(&keys, &config.audience, "moe@gmail.com", Some("n-0S6_WzA2Mj".to_string()), auth_time);
+        let claims = verify(&keys, &id_token).unwrap();
+        assert_eq!(claims.iss.unwrap(), "https://infotamia.com");
+        assert_eq!(claims.aud.unwrap(), "infotamia");
+        assert_eq!(claims.sub.unwrap(), "moe@gmail.com");
+        assert_eq!(claims.nonce.unwrap(), "n-0S6_WzA2Mj");
+        assert_eq!(claims.auth_time.unwrap(), auth_time);
+        assert!(claims.session_type.is_none());
+    }
+
+    #[test]
+    fn test_access_token_is_short_lived() {
+        let config = test_oidc_configuration();
+        let keys = test_key_manager();
+        let claims = verify(&keys, &config.access_token(&keys, "moe@gmail.com", SessionType::USER, None, vec![Permission::GLOBAL_READ], "openid".to_string())).unwrap();
+        assert_eq!(claims.exp - claims.iat, 900);
+        assert_eq!(claims.session_type.unwrap(), SessionType::USER);
+        assert_eq!(claims.permissions, vec![Permission::GLOBAL_READ]);
+    }
+
+    #[test]
+    fn test_scope_is_narrowed() {
+        let config = test_oidc_configuration();
+        assert_eq!(config.grant_scope(None).unwrap(), "openid email devices:read devices:write");
+        assert_eq!(config.grant_scope(Some("devices:read devices:read")).unwrap(), "devices:read");
+        assert!(config.grant_scope(Some("devices:admin")).is_none());
+        assert!(config.grant_scope(Some(" ")).is_none());
+        assert_eq!(narrow_scope("openid devices:read", None).unwrap(), "openid devices:read");
+        assert!(narrow_scope("devices:read", Some("devices:write")).is_none());
+    }
+
+    #[test]
+    fn test_client_scope() {
+        let granted = "openid email devices:read devices:write";
+        assert_eq!(client_scope(granted, "openid devices:read", None).unwrap(), "openid devices:read");
+        assert_eq!(client_scope(granted, "openid devices:read", Some("devices:read")).unwrap(), "devices:read");
+        assert!(client_scope(granted, "openid devices:read", Some("devices:write")).is_none());
+        assert!(client_scope("openid", "devices:read", None).is_none());
+    }
+}
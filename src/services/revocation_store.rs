@@ -0,0 +1,289 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use log::error;
+use sqlx::{Done, MySqlPool, Row};
+
+use crate::services::jwt_service::JwtClaims;
+
+/// tokens that must no longer be accepted even though they verify and have not expired
+#[async_trait]
+pub trait RevocationStore: Send + Sync {
+    /// reject every token of the subject issued before now, the cutoff is kept in milliseconds
+    async fn revoke_subject(&self, subject: &str);
+
+    /// reject one token by its jwt_id, the entry is only kept until the token expires anyway
+    async fn revoke_token(&self, jwt_id: &str, expires_at: usize);
+
+    /// true when the token was revoked
+    async fn is_revoked(&self, claims: &JwtClaims) -> bool;
+
+    /// true when every session of the subject issued at issued_at_ms was ended since
+    async fn is_subject_revoked(&self, subject: &str, issued_at_ms: i64) -> bool;
+
+    /// drop revocations no token they reject can outlive, returns how many were removed
+    async fn evict_expired(&self) -> u64;
+}
+
+/// per process store, fine for a single instance and for tests
+pub struct InMemoryRevocationStore {
+    subject_cutoffs: RwLock<HashMap<String, i64>>,
+    revoked_tokens: RwLock<HashMap<String, usize>>,
+}
+
+impl InMemoryRevocationStore {
+    pub fn new() -> Self {
+        InMemoryRevocationStore {
+            subject_cutoffs: RwLock::new(HashMap::new()),
+            revoked_tokens: RwLock::new(HashMap::new()),
+        }
+    }
+
+    fn is_token_revoked(&self, claims: &JwtClaims) -> bool {
+        let jwt_id = match claims.jwt_id {
+            None => return false,
+            Some(ref jwt_id) => jwt_id
+        };
+        match self.revoked_tokens.read() {
+            Ok(tokens) => tokens.contains_key(jwt_id),
+            Err(_) => true
+        }
+    }
+
+    /// add the rows of the shared table, revocations only recorded here are kept until they expire
+    fn merge_tokens(&self, revoked: HashMap<String, usize>) {
+        if let Ok(mut tokens) = self.revoked_tokens.write() {
+            tokens.extend(revoked);
+        }
+    }
+
+    /// add the rows of the shared table, the later cutoff of a subject wins
+    fn merge_subjects(&self, revoked: HashMap<String, i64>) {
+        if let Ok(mut cutoffs) = self.subject_cutoffs.write() {
+            for (subject, revoked_before) in revoked {
+                let cutoff = cutoffs.entry(subject).or_insert(revoked_before);
+                *cutoff = (*cutoff).max(revoked_before);
+            }
+        }
+    }
+
+    /// drop the cutoffs no token issued before them can outlive
+    fn evict_subjects(&self, older_than_ms: i64) {
+        if let Ok(mut cutoffs) = self.subject_cutoffs.write() {
+            cutoffs.retain(|_, revoked_before| *revoked_before > older_than_ms);
+        }
+    }
+}
+
+#[async_trait]
+impl RevocationStore for InMemoryRevocationStore {
+    async fn revoke_subject(&self, subject: &str) {
+        if let Ok(mut cutoffs) = self.subject_cutoffs.write() {
+            cutoffs.insert(subject.to_string(), Utc::now().timestamp_millis());
+        }
+    }
+
+    async fn revoke_token(&self, jwt_id: &str, expires_at: usize) {
+        if let Ok(mut tokens) = self.revoked_tokens.write() {
+            tokens.insert(jwt_id.to_string(), expires_at);
+        }
+    }
+
+    async fn is_revoked(&self, claims: &JwtClaims) -> bool {
+        if self.is_token_revoked(claims) {
+            return true;
+        }
+        match claims.sub {
+            None => false,
+            Some(ref subject) => self.is_subject_revoked(subject, claims.issued_at_ms()).await
+        }
+    }
+
+    async fn is_subject_revoked(&self, subject: &str, issued_at_ms: i64) -> bool {
+        match self.subject_cutoffs.read() {
+            Ok(cutoffs) => {
+                cutoffs.get(subject).is_some_and(|cutoff| issued_at_ms < *cutoff)
+            }
+            Err(_) => true
+        }
+    }
+
+    async fn evict_expired(&self) -> u64 {
+        match self.revoked_tokens.write() {
+            Ok(mut tokens) => {
+                let before = tokens.len();
+                let now = Utc::now().timestamp() as usize;
+                tokens.retain(|_, expires_at| *expires_at > now);
+                (before - tokens.len()) as u64
+            }
+            Err(_) => 0
+        }
+    }
+}
+
+/// shared store backed by the subject_revocation and token_revocation tables so a revocation
+/// reaches every instance behind the load balancer.
+/// requests are checked against an in-memory copy of both tables, revocations made on this instance
+/// apply at once and the ones made elsewhere once evict_expired reloads the copy.
+/// a subject revocation, revoked_before in milliseconds, is kept for max_token_lifetime_seconds, no token issued before it outlives that
+pub struct MySqlRevocationStore {
+    pool: MySqlPool,
+    cache: InMemoryRevocationStore,
+    max_token_lifetime_seconds: i64,
+}
+
+impl MySqlRevocationStore {
+    pub fn new(pool: MySqlPool, max_token_lifetime_seconds: i64) -> Self {
+        MySqlRevocationStore {
+            pool,
+            cache: InMemoryRevocationStore::new(),
+            max_token_lifetime_seconds,
+        }
+    }
+}
+
+#[async_trait]
+impl RevocationStore for MySqlRevocationStore {
+    async fn revoke_subject(&self, subject: &str) {
+        let revoked_before = Utc::now().timestamp_millis();
+        self.cache.merge_subjects(vec![(subject.to_string(), revoked_before)].into_iter().collect());
+        let done = sqlx::query("INSERT INTO subject_revocation(subject, revoked_before) VALUES(?,?) ON DUPLICATE KEY UPDATE revoked_before = VALUES(revoked_before)")
+            .bind(subject)
+            .bind(revoked_before)
+            .execute(&self.pool).await;
+        if let Err(err) = done {
+            error!("error revoking sessions of {} {}", subject, err);
+        }
+    }
+
+    async fn revoke_token(&self, jwt_id: &str, expires_at: usize) {
+        self.cache.revoke_token(jwt_id, expires_at).await;
+        let done = sqlx::query("INSERT IGNORE INTO token_revocation(jwt_id, expires_at) VALUES(?,?)")
+            .bind(jwt_id)
+            .bind(expires_at as i64)
+            .execute(&self.pool).await;
+        if let Err(err) = done {
+            error!("error revoking token {} {}", jwt_id, err);
+        }
+    }
+
+    async fn is_revoked(&self, claims: &JwtClaims) -> bool {
+        self.cache.is_revoked(claims).await
+    }
+
+    async fn is_subject_revoked(&self, subject: &str, issued_at_ms: i64) -> bool {
+        self.cache.is_subject_revoked(subject, issued_at_ms).await
+    }
+
+    async fn evict_expired(&self) -> u64 {
+        let now = Utc::now().timestamp();
+        let mut evicted = match sqlx::query("DELETE FROM token_revocation WHERE expires_at <= ?")
+            .bind(now)
+            .execute(&self.pool).await {
+            Ok(d) => d.rows_affected(),
+            Err(err) => {
+                error!("error evicting token revocations {}", err);
+                0
+            }
+        };
+        evicted += match sqlx::query("DELETE FROM subject_revocation WHERE revoked_before <= ?")
+            .bind((now - self.max_token_lifetime_seconds) * 1000)
+            .execute(&self.pool).await {
+            Ok(d) => d.rows_affected(),
+            Err(err) => {
+                error!("error evicting subject revocations {}", err);
+                0
+            }
+        };
+        self.cache.evict_expired().await;
+        self.cache.evict_subjects((now - self.max_token_lifetime_seconds) * 1000);
+        let rows = sqlx::query("SELECT subject, revoked_before FROM subject_revocation")
+            .fetch_all(&self.pool).await;
+        match rows {
+            Ok(rows) => {
+                let revoked = rows.iter()
+                    .map(|r| (r.get::<String, _>("subject"), r.get::<i64, _>("revoked_before")))
+                    .collect();
+                self.cache.merge_subjects(revoked);
+            }
+            Err(err) => error!("error reloading subject revocations {}", err)
+        }
+        let rows = sqlx::query("SELECT jwt_id, expires_at FROM token_revocation")
+            .fetch_all(&self.pool).await;
+        match rows {
+            Ok(rows) => {
+                let revoked = rows.iter()
+                    .map(|r| (r.get::<String, _>("jwt_id"), r.get::<i64, _>("expires_at") as usize))
+                    .collect();
+                self.cache.merge_tokens(revoked);
+            }
+            // keep the copy we have, it is only ever missing newer revocations
+            Err(err) => error!("error reloading token revocations {}", err)
+        }
+        evicted
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::services::key_manager::test_claims;
+
+    fn claims(subject: &str, iat: usize) -> JwtClaims {
+        JwtClaims { sub: Some(subject.to_string()), iat, exp: iat + 60, ..test_claims() }
+    }
+
+    #[actix_rt::test]
+    async fn test_revoke_subject() {
+        let store = InMemoryRevocationStore::new();
+        let before = Utc::now().timestamp() as usize - 10;
+        assert!(!store.is_revoked(&claims("moe@gmail.com", before)).await);
+
+        store.revoke_subject("moe@gmail.com").await;
+        assert!(store.is_revoked(&claims("moe@gmail.com", before)).await);
+        assert!(!store.is_revoked(&claims("moe@gmail.com", before + 20)).await);
+        assert!(!store.is_revoked(&claims("ahmed@gmail.com", before)).await);
+    }
+
+    #[actix_rt::test]
+    async fn test_login_in_the_revoked_second() {
+        let store = InMemoryRevocationStore::new();
+        let now = Utc::now();
+        let session = |iat_ms| JwtClaims { iat_ms: Some(iat_ms), ..claims("moe@gmail.com", now.timestamp() as usize) };
+        store.revoke_subject("moe@gmail.com").await;
+        assert!(store.is_revoked(&session(now.timestamp_millis() - 1)).await);
+        // a login right after a password change can share its second
+        assert!(!store.is_revoked(&session(Utc::now().timestamp_millis() + 1)).await);
+    }
+
+    #[actix_rt::test]
+    async fn test_reloaded_subject_cutoffs() {
+        let cache = InMemoryRevocationStore::new();
+        let now = Utc::now().timestamp_millis();
+        cache.merge_subjects(vec![("moe@gmail.com".to_string(), now)].into_iter().collect());
+        // an older row of the shared table does not undo a newer local revocation
+        cache.merge_subjects(vec![("moe@gmail.com".to_string(), now - 1000)].into_iter().collect());
+        assert!(cache.is_subject_revoked("moe@gmail.com", now - 500).await);
+
+        cache.evict_subjects(now);
+        assert!(!cache.is_subject_revoked("moe@gmail.com", now - 500).await);
+    }
+
+    #[actix_rt::test]
+    async fn test_revoke_token() {
+        let store = InMemoryRevocationStore::new();
+        let now = Utc::now().timestamp() as usize;
+        let logged_out = claims("moe@gmail.com", now);
+        let other_session = claims("moe@gmail.com", now);
+        store.revoke_token(logged_out.jwt_id.as_ref().unwrap(), logged_out.exp).await;
+        assert!(store.is_revoked(&logged_out).await);
+        assert!(!store.is_revoked(&other_session).await);
+
+        // entries only live as long as the token would have
+        store.revoke_token("expired", now - 1).await;
+        assert_eq!(store.evict_expired().await, 1);
+        assert!(store.is_revoked(&logged_out).await);
+    }
+}
@@ -0,0 +1,265 @@
+use std::{fs, process};
+use std::io::{Error, ErrorKind};
+
+use num_bigint::BigUint;
+use num_traits::Zero;
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::entities::srp::srp_group::SrpGroup;
+
+/// which SrpSessionStore implementation holds pending handshakes
+#[derive(Debug, Deserialize, PartialEq, Clone, Copy)]
+pub enum SrpSessionStoreKind {
+    MEMORY, MYSQL
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SrpConfiguration {
+    #[serde(default = "default_session_store")]
+    pub session_store: SrpSessionStoreKind,
+    #[serde(default = "default_session_ttl_seconds")]
+    pub session_ttl_seconds: u64,
+    #[serde(default = "default_max_pending_sessions")]
+    pub max_pending_sessions: usize,
+    #[serde(default = "default_eviction_interval_seconds")]
+    pub eviction_interval_seconds: u64,
+    /// group new registrations must use, existing users keep the group stored on their row
+    #[serde(default = "default_registration_group")]
+    pub registration_group: SrpGroup,
+    /// server secret unknown identities get their fake salt and verifier from. it is never read from
+    /// this file, new() loads it from SRP_FAKE_CREDENTIALS_SECRET or from fake_credentials_secret_path
+    #[serde(skip)]
+    pub fake_credentials_secret: String,
+    /// file holding the secret, a mounted docker secret for example
+    pub fake_credentials_secret_path: Option<String>,
+    #[serde(default = "default_reset_token_ttl_seconds")]
+    pub reset_token_ttl_seconds: u64,
+}
+
+fn default_session_store() -> SrpSessionStoreKind { SrpSessionStoreKind::MEMORY }
+fn default_session_ttl_seconds() -> u64 { 60 }
+fn default_max_pending_sessions() -> usize { 10_000 }
+fn default_eviction_interval_seconds() -> u64 { 30 }
+fn default_registration_group() -> SrpGroup { SrpGroup::RFC5054_2048 }
+fn default_reset_token_ttl_seconds() -> u64 { 3600 }
+
+impl SrpConfiguration {
+    pub fn new() -> Self {
+        let srp_config = fs::read_to_string("./srp_configuration.json").unwrap_or_else(|err| {
+            eprintln!("error reading file {}", err);
+            process::exit(1);
+        });
+
+        let mut config: SrpConfiguration = serde_json::from_str(srp_config.as_str()).unwrap_or_else(|err| {
+            eprintln!("error deserializing file content {}", err);
+            process::exit(1);
+        });
+        config.fake_credentials_secret = load_fake_credentials_secret(config.fake_credentials_secret_path.as_ref()).unwrap_or_else(|err| {
+            eprintln!("error loading fake_credentials_secret {}", err);
+            process::exit(1);
+        });
+        config
+    }
+}
+
+const FAKE_CREDENTIALS_SECRET_ENV: &str = "SRP_FAKE_CREDENTIALS_SECRET";
+const PLACEHOLDER_SECRET: &str = "replace-with-a-long-random-secret";
+
+/// the environment wins over the file, whoever learns the secret can tell registered identities apart
+fn load_fake_credentials_secret(path: Option<&String>) -> Result<String, String> {
+    let secret = match (std::env::var(FAKE_CREDENTIALS_SECRET_ENV), path) {
+        (Ok(secret), _) => secret,
+        (Err(_), Some(path)) => fs::read_to_string(path).map_err(|err| format!("error reading {} {}", path, err))?,
+        (Err(_), None) => return Err(format!("set {} or fake_credentials_secret_path", FAKE_CREDENTIALS_SECRET_ENV))
+    };
+    check_fake_credentials_secret(secret.trim())
+}
+
+fn check_fake_credentials_secret(secret: &str) -> Result<String, String> {
+    if secret == PLACEHOLDER_SECRET {
+        return Err("the placeholder secret must be replaced".to_string());
+    }
+    if secret.len() < 32 {
+        return Err("the secret must be at least 32 characters".to_string());
+    }
+    Ok(secret.to_string())
+}
+
+/// server side of one srp-6a login, wire compatible with rust_srp::SrpClient.
+/// unlike rust_srp::SrpServer the state is plain data so it can be parked in any SrpSessionStore
+/// between step 1 and step 2, all numbers are kept as base 10 strings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SrpHandshake {
+    pub identity: String,
+    n: String,
+    g: String,
+    salt: String,
+    verifier: String,
+    public_a: String,
+    private_b: String,
+    public_b: String,
+}
+
+impl SrpHandshake {
+    /// step 1, compute B = k*v + g^b for the client public value A
+    pub fn start(identity: String, public_a: BigUint, salt: BigUint, verifier: BigUint, n: BigUint, g: BigUint) -> Result<Self, Error> {
+        if (&public_a % &n).is_zero() {
+            return Err(Error::new(ErrorKind::InvalidData, "bad client public value!"));
+        }
+        let k = compute_k(&n, &g);
+        let private_b = rust_srp::bigint_helper::generate_random_256bit_bigint();
+        let public_b = k * &verifier + g.modpow(&private_b, &n);
+        Ok(SrpHandshake {
+            identity,
+            n: n.to_string(),
+            g: g.to_string(),
+            salt: salt.to_string(),
+            verifier: verifier.to_string(),
+            public_a: public_a.to_string(),
+            private_b: private_b.to_string(),
+            public_b: public_b.to_string(),
+        })
+    }
+
+    #[cfg(test)]
+    pub fn salt(&self) -> BigUint {
+        to_bigint(&self.salt)
+    }
+
+    pub fn public_b(&self) -> BigUint {
+        to_bigint(&self.public_b)
+    }
+
+    /// step 2, validate client evidence m1 and return server evidence m2
+    pub fn finish(self, m1: &BigUint) -> Result<BigUint, Error> {
+        let n = to_bigint(&self.n);
+        let public_a = to_bigint(&self.public_a);
+        let public_b = to_bigint(&self.public_b);
+        let verifier = to_bigint(&self.verifier);
+        let private_b = to_bigint(&self.private_b);
+
+        let u = hash(&[&public_a.to_bytes_be(), &public_b.to_bytes_be()]);
+        if u.is_zero() {
+            return Err(Error::new(ErrorKind::InvalidData, "bad client credentials!"));
+        }
+        // S = (A * v^u)^b
+        let s = (public_a.clone() * verifier.modpow(&u, &n)).modpow(&private_b, &n);
+        let key = hash(&[&s.to_bytes_be()]);
+        let m1_computed = hash(&[&public_a.to_bytes_be(), &public_b.to_bytes_be(), &key.to_bytes_be()]);
+        if !openssl::memcmp::eq(&pad(&m1_computed), &pad(m1)) {
+            return Err(Error::new(ErrorKind::InvalidData, "bad client credentials!"));
+        }
+        Ok(hash(&[&public_a.to_bytes_be(), &m1.to_bytes_be(), &key.to_bytes_be()]))
+    }
+}
+
+/// deterministic salt and verifier for an identity that has no srp credentials.
+/// step 1 answers unknown identities with these so the response cannot be told apart
+/// from a registered user, and since nobody knows the password behind the verifier step 2 fails
+pub fn fake_credentials(secret: &str, identity: &str, group: SrpGroup) -> (BigUint, BigUint) {
+    let salt = hmac(secret, &[b"salt:", identity.as_bytes()]);
+    let x = hmac(secret, &[b"verifier:", identity.as_bytes()]);
+    let verifier = group.g().modpow(&x, &group.n());
+    (salt, verifier)
+}
+
+fn hmac(secret: &str, args: &[&[u8]]) -> BigUint {
+    let key = PKey::hmac(secret.as_bytes()).unwrap();
+    let mut signer = Signer::new(MessageDigest::sha256(), &key).unwrap();
+    for arg in args {
+        signer.update(arg).unwrap();
+    }
+    BigUint::from_bytes_be(&signer.sign_to_vec().unwrap())
+}
+
+/// k = H(N | g)
+fn compute_k(n: &BigUint, g: &BigUint) -> BigUint {
+    hash(&[&n.to_bytes_be(), &g.to_bytes_be()])
+}
+
+fn hash(args: &[&[u8]]) -> BigUint {
+    let mut hasher = Sha256::new();
+    for arg in args {
+        hasher.update(arg);
+    }
+    BigUint::from_bytes_be(hasher.finalize().as_slice())
+}
+
+/// left pad to a sha256 digest length so the evidence comparison runs in constant time
+fn pad(value: &BigUint) -> Vec<u8> {
+    let bytes = value.to_bytes_be();
+    let mut padded = vec![0u8; 32usize.saturating_sub(bytes.len())];
+    padded.extend_from_slice(&bytes);
+    padded
+}
+
+fn to_bigint(value: &str) -> BigUint {
+    BigUint::parse_bytes(value.as_bytes(), 10).unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use rust_srp::SrpClient;
+
+    use super::*;
+
+    fn group() -> (BigUint, BigUint) {
+        let n = BigUint::parse_bytes(b"B97F8C656C3DF7179C2B805BBCB3A0DC4B0B6926BF66D0A3C63CF6015625CAF9A4DB4BBE7EB34253FAB0E475A6ACFAE49FD5F22C47A71B5532911B69FE7DF4F8ACEE2F7785D75866CF6D213286FC7EBBBE3BE411ECFA10A70F0C8463DC1182C6F9B6F7666C8691B3D1AB6FD78E9CBF8AAE719EA75CA02BE87AE445C698BF0413", 16).unwrap();
+        let g = BigUint::parse_bytes(b"2", 10).unwrap();
+        (n, g)
+    }
+
+    #[test]
+    fn test_fake_credentials_secret_must_be_replaced() {
+        assert!(check_fake_credentials_secret(PLACEHOLDER_SECRET).is_err());
+        assert!(check_fake_credentials_secret("short").is_err());
+        assert!(check_fake_credentials_secret("f2b1c1e0b0d64a0f9c3e8b7a6d5c4b3a").is_ok());
+    }
+
+    fn handshake() -> (SrpClient, SrpHandshake) {
+        let (n, g) = group();
+        let salt = rust_srp::bigint_helper::generate_random_256bit_bigint();
+        let verifier = g.modpow(&rust_srp::compute_x(&salt, "12345678"), &n);
+        let mut client = SrpClient::new(n.clone(), g.clone());
+        let public_a = client.step_1("moe@gmail.com".to_string(), "12345678".to_string()).unwrap();
+        let handshake = SrpHandshake::start("moe@gmail.com".to_string(), public_a, salt, verifier, n, g).unwrap();
+        (client, handshake)
+    }
+
+    #[test]
+    fn test_handshake_with_srp_client() {
+        let (mut client, handshake) = handshake();
+        // survive a round trip through a store
+        let handshake: SrpHandshake = serde_json::from_str(&serde_json::to_string(&handshake).unwrap()).unwrap();
+        let m1 = client.step_2(handshake.salt(), handshake.public_b()).unwrap();
+        let m2 = handshake.finish(&m1).unwrap();
+        client.step_3(m2).unwrap();
+    }
+
+    #[test]
+    fn test_handshake_forged_evidence() {
+        let (_, handshake) = handshake();
+        assert!(handshake.finish(&BigUint::from(42u32)).is_err());
+    }
+
+    #[test]
+    fn test_fake_credentials_are_deterministic() {
+        let group = SrpGroup::RFC5054_2048;
+        let (salt, verifier) = fake_credentials("secret", "ghost@gmail.com", group);
+        assert_eq!((salt.clone(), verifier.clone()), fake_credentials("secret", "ghost@gmail.com", group));
+        assert_ne!(salt, fake_credentials("secret", "other@gmail.com", group).0);
+        assert_ne!(salt, fake_credentials("other secret", "ghost@gmail.com", group).0);
+        assert!(verifier < group.n());
+    }
+
+    #[test]
+    fn test_handshake_rejects_zero_public_a() {
+        let (n, g) = group();
+        let one = BigUint::from(1u32);
+        assert!(SrpHandshake::start("moe@gmail.com".to_string(), n.clone(), one.clone(), one, n, g).is_err());
+    }
+}
@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use log::{error, warn};
+use sqlx::{Done, MySqlPool, Row};
+use uuid::Uuid;
+
+use crate::services::srp_service::{SrpConfiguration, SrpHandshake, SrpSessionStoreKind};
+
+/// pending srp handshakes between step 1 and step 2.
+/// every handshake gets its own id so concurrent logins of one identity do not clobber each other
+#[async_trait]
+pub trait SrpSessionStore: Send + Sync {
+    /// park a handshake, returns its id or None when the store is full
+    async fn put(&self, handshake: SrpHandshake) -> Option<String>;
+
+    /// remove and return a pending handshake, a handshake can only be taken once
+    async fn take(&self, handshake_id: &str) -> Option<SrpHandshake>;
+
+    /// drop expired handshakes, returns how many were removed
+    async fn evict_expired(&self) -> u64;
+}
+
+/// longest a handshake may sit in the srp_session table, see MySqlSrpSessionStore
+pub const MAX_SHARED_SESSION_TTL_SECONDS: u64 = 120;
+
+/// build the store selected in srp_configuration.json
+pub fn build_session_store(config: &SrpConfiguration, pool: &MySqlPool) -> Arc<dyn SrpSessionStore> {
+    let ttl = Duration::from_secs(config.session_ttl_seconds);
+    match config.session_store {
+        SrpSessionStoreKind::MEMORY => {
+            Arc::new(InMemorySrpSessionStore::new(ttl, config.max_pending_sessions))
+        }
+        SrpSessionStoreKind::MYSQL => {
+            if config.session_ttl_seconds > MAX_SHARED_SESSION_TTL_SECONDS {
+                warn!("session_ttl_seconds {} is too long for the MYSQL session store, using {}", config.session_ttl_seconds, MAX_SHARED_SESSION_TTL_SECONDS);
+            }
+            let ttl = ttl.min(Duration::from_secs(MAX_SHARED_SESSION_TTL_SECONDS));
+            Arc::new(MySqlSrpSessionStore::new(pool.clone(), ttl, config.max_pending_sessions))
+        }
+    }
+}
+
+struct PendingHandshake {
+    handshake: SrpHandshake,
+    expires_at: Instant,
+}
+
+/// per process store, fine for a single instance
+pub struct InMemorySrpSessionStore {
+    sessions: Mutex<HashMap<String, PendingHandshake>>,
+    ttl: Duration,
+    max_sessions: usize,
+}
+
+impl InMemorySrpSessionStore {
+    pub fn new(ttl: Duration, max_sessions: usize) -> Self {
+        InMemorySrpSessionStore {
+            sessions: Mutex::new(HashMap::new()),
+            ttl,
+            max_sessions,
+        }
+    }
+}
+
+#[async_trait]
+impl SrpSessionStore for InMemorySrpSessionStore {
+    async fn put(&self, handshake: SrpHandshake) -> Option<String> {
+        let mut sessions = self.sessions.lock().ok()?;
+        let now = Instant::now();
+        if sessions.len() >= self.max_sessions {
+            sessions.retain(|_, pending| pending.expires_at > now);
+        }
+        if sessions.len() >= self.max_sessions {
+            return None;
+        }
+        let handshake_id = Uuid::new_v4().to_string();
+        sessions.insert(handshake_id.clone(), PendingHandshake {
+            handshake,
+            expires_at: now + self.ttl,
+        });
+        Some(handshake_id)
+    }
+
+    async fn take(&self, handshake_id: &str) -> Option<SrpHandshake> {
+        let pending = self.sessions.lock().ok()?.remove(handshake_id)?;
+        if pending.expires_at > Instant::now() {
+            Some(pending.handshake)
+        } else {
+            None
+        }
+    }
+
+    async fn evict_expired(&self) -> u64 {
+        match self.sessions.lock() {
+            Ok(mut sessions) => {
+                let before = sessions.len();
+                let now = Instant::now();
+                sessions.retain(|_, pending| pending.expires_at > now);
+                (before - sessions.len()) as u64
+            }
+            Err(_) => 0
+        }
+    }
+}
+
+/// shared store backed by the srp_session table, lets step 2 land on any
+/// instance behind the load balancer.
+/// the handshake is stored as plain json, private_b and the verifier included. whoever reads the
+/// table while a handshake is pending can complete that login, so its ttl is capped at
+/// MAX_SHARED_SESSION_TTL_SECONDS and the row is deleted when step 2 takes it. access to the
+/// table must be as restricted as access to the verifiers in the user table
+pub struct MySqlSrpSessionStore {
+    pool: MySqlPool,
+    ttl: Duration,
+    max_sessions: usize,
+}
+
+impl MySqlSrpSessionStore {
+    pub fn new(pool: MySqlPool, ttl: Duration, max_sessions: usize) -> Self {
+        MySqlSrpSessionStore {
+            pool,
+            ttl,
+            max_sessions,
+        }
+    }
+}
+
+#[async_trait]
+impl SrpSessionStore for MySqlSrpSessionStore {
+    async fn put(&self, handshake: SrpHandshake) -> Option<String> {
+        let pending = sqlx::query("SELECT COUNT(*) AS pending FROM srp_session WHERE expires_at > ?")
+            .bind(Utc::now().timestamp())
+            .fetch_one(&self.pool).await;
+        match pending {
+            Ok(row) => {
+                let pending: i64 = row.get("pending");
+                if pending as usize >= self.max_sessions {
+                    return None;
+                }
+            }
+            Err(err) => {
+                error!("error counting srp sessions {}", err);
+                return None;
+            }
+        }
+
+        let handshake_id = Uuid::new_v4().to_string();
+        let expires_at = Utc::now().timestamp() + self.ttl.as_secs() as i64;
+        let done = sqlx::query("INSERT INTO srp_session(id, identity, handshake, expires_at) VALUES(?,?,?,?)")
+            .bind(&handshake_id)
+            .bind(&handshake.identity)
+            .bind(serde_json::to_string(&handshake).unwrap())
+            .bind(expires_at)
+            .execute(&self.pool).await;
+        match done {
+            Ok(_) => Some(handshake_id),
+            Err(err) => {
+                error!("error storing srp session {}", err);
+                None
+            }
+        }
+    }
+
+    async fn take(&self, handshake_id: &str) -> Option<SrpHandshake> {
+        let row = sqlx::query("SELECT handshake, expires_at FROM srp_session WHERE id = ?")
+            .bind(handshake_id)
+            .fetch_one(&self.pool).await.ok()?;
+
+        // only the request that deletes the row may use it
+        let done = sqlx::query("DELETE FROM srp_session WHERE id = ?")
+            .bind(handshake_id)
+            .execute(&self.pool).await.ok()?;
+        if done.rows_affected() != 1 {
+            return None;
+        }
+
+        let expires_at: i64 = row.get("expires_at");
+        if expires_at <= Utc::now().timestamp() {
+            return None;
+        }
+        let handshake: String = row.get("handshake");
+        serde_json::from_str(&handshake).ok()
+    }
+
+    async fn evict_expired(&self) -> u64 {
+        let done = sqlx::query("DELETE FROM srp_session WHERE expires_at <= ?")
+            .bind(Utc::now().timestamp())
+            .execute(&self.pool).await;
+        match done {
+            Ok(d) => d.rows_affected(),
+            Err(err) => {
+                error!("error evicting srp sessions {}", err);
+                0
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use num_bigint::BigUint;
+
+    use super::*;
+
+    fn handshake() -> SrpHandshake {
+        let n = BigUint::parse_bytes(b"B97F8C656C3DF7179C2B805BBCB3A0DC4B0B6926BF66D0A3C63CF6015625CAF9A4DB4BBE7EB34253FAB0E475A6ACFAE49FD5F22C47A71B5532911B69FE7DF4F8ACEE2F7785D75866CF6D213286FC7EBBBE3BE411ECFA10A70F0C8463DC1182C6F9B6F7666C8691B3D1AB6FD78E9CBF8AAE719EA75CA02BE87AE445C698BF0413", 16).unwrap();
+        let g = BigUint::parse_bytes(b"2", 10).unwrap();
+        let public_a = g.modpow(&BigUint::from(12345u32), &n);
+        SrpHandshake::start("moe@gmail.com".to_string(), public_a, BigUint::from(7u32), BigUint::from(11u32), n, g).unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn test_handshake_taken_once() {
+        let store = InMemorySrpSessionStore::new(Duration::from_secs(60), 10);
+        let first = store.put(handshake()).await.unwrap();
+        let second = store.put(handshake()).await.unwrap();
+        assert_ne!(first, second);
+        assert!(store.take(&first).await.is_some());
+        assert!(store.take(&first).await.is_none());
+        assert!(store.take(&second).await.is_some());
+    }
+
+    #[actix_rt::test]
+    async fn test_handshake_expires() {
+        let store = InMemorySrpSessionStore::new(Duration::from_millis(0), 10);
+        let id = store.put(handshake()).await.unwrap();
+        assert!(store.take(&id).await.is_none());
+
+        store.put(handshake()).await.unwrap();
+        assert_eq!(store.evict_expired().await, 1);
+    }
+
+    #[actix_rt::test]
+    async fn test_store_is_bounded() {
+        let store = InMemorySrpSessionStore::new(Duration::from_secs(60), 2);
+        assert!(store.put(handshake()).await.is_some());
+        assert!(store.put(handshake()).await.is_some());
+        assert!(store.put(handshake()).await.is_none());
+    }
+}
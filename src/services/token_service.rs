@@ -0,0 +1,156 @@
+use std::future::Future;
+
+use chrono::{Duration, Utc};
+use log::warn;
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use sqlx::MySqlPool;
+use uuid::Uuid;
+
+use crate::daos::refresh_token_dao::RefreshTokenDao;
+use crate::entities::refresh_token_entity::RefreshTokenFamily;
+use crate::services::jwt_service::SessionType;
+use crate::services::oidc_service::narrow_scope;
+use crate::services::revocation_store::RevocationStore;
+
+/// result of presenting a refresh token
+#[derive(Debug, PartialEq)]
+pub enum RefreshOutcome {
+    /// the token was live, it is used up now and replaced by refresh_token
+    /// scope is the one of the new access token, the refresh token keeps the scope of the login
+    Rotated { user_id: u32, email: String, session_type: SessionType, scope: String, refresh_token: String },
+    /// the token had been rotated before, somebody replayed it and its family is revoked
+    Reused,
+    /// the requested scope is not within the granted one, the token is left unused
+    InvalidScope,
+    /// unknown, expired or revoked, also when the sessions of its user were ended after its login
+    Invalid,
+}
+
+pub struct TokenService<'a> {
+    refresh_token_dao: RefreshTokenDao<'a>
+}
+
+impl <'a> TokenService<'a> {
+    pub fn new(conn: &'a MySqlPool) -> Self {
+        TokenService {
+            refresh_token_dao: RefreshTokenDao::new(conn)
+        }
+    }
+
+    /// issue an opaque refresh token for a fresh login, only its hash is stored.
+    /// client_id names the oauth client it is issued to, None for our own logins
+    pub async fn issue_refresh_token(&mut self, user_id: u32, session_type: SessionType, scope: &str, client_id: Option<&String>, ttl: Duration) -> Option<String> {
+        let family = RefreshTokenFamily {
+            family_id: Uuid::new_v4().to_string(),
+            issued_at: Utc::now().timestamp_millis(),
+            user_id,
+            session_type,
+            scope: scope.to_string(),
+            client_id: client_id.cloned(),
+        };
+        self.issue_in_family(&family, ttl).await
+    }
+
+    /// swap a refresh token for a new one of the same family.
+    /// a token that was already used means two parties hold it, so the whole family is revoked.
+    /// client_id is the authenticated client presenting it, a token is only rotated for the client it was issued to
+    pub async fn rotate(&mut self, refresh_token: &String, client_id: Option<&String>, requested_scope: Option<&str>, ttl: Duration, revocation_store: &dyn RevocationStore) -> RefreshOutcome {
+        let now = Utc::now().timestamp();
+        let entity = match self.refresh_token_dao.find_by_hash(&hash_token(refresh_token)).await {
+            None => return RefreshOutcome::Invalid,
+            Some(entity) => entity
+        };
+        if entity.revoked_at.is_some() || entity.expires_at <= now || entity.client_id.as_ref() != client_id {
+            return RefreshOutcome::Invalid;
+        }
+        // the password changed or an admin ended the sessions, and the family was not revoked with them
+        if revocation_store.is_subject_revoked(&entity.email, entity.family_issued_at).await {
+            self.refresh_token_dao.revoke_family(&entity.family_id, now).await;
+            return RefreshOutcome::Invalid;
+        }
+        let scope = match narrow_scope(&entity.scope, requested_scope) {
+            None => return RefreshOutcome::InvalidScope,
+            Some(scope) => scope
+        };
+        if !consume_once(entity.used_at, self.refresh_token_dao.mark_used(entity.id, now)).await {
+            warn!("refresh token reuse in family {}, revoking it", entity.family_id);
+            self.refresh_token_dao.revoke_family(&entity.family_id, now).await;
+            return RefreshOutcome::Reused;
+        }
+        match self.issue_in_family(&entity.family(), ttl).await {
+            Some(refresh_token) => RefreshOutcome::Rotated {
+                user_id: entity.user_id,
+                email: entity.email,
+                session_type: entity.session_type,
+                scope,
+                refresh_token
+            },
+            None => RefreshOutcome::Invalid
+        }
+    }
+
+    /// revoke the family of a refresh token of the user, used on logout.
+    /// a token of somebody else is left alone, false like for an unknown one
+    pub async fn revoke(&mut self, refresh_token: &String, email: &str) -> bool {
+        match self.refresh_token_dao.find_by_hash(&hash_token(refresh_token)).await {
+            Some(entity) if entity.email == email => self.refresh_token_dao.revoke_family(&entity.family_id, Utc::now().timestamp()).await,
+            _ => false
+        }
+    }
+
+    /// revoke every refresh token of the user
+    pub async fn revoke_user(&mut self, user_id: u32) -> bool {
+        self.refresh_token_dao.revoke_user(user_id, Utc::now().timestamp()).await
+    }
+
+    async fn issue_in_family(&mut self, family: &RefreshTokenFamily, ttl: Duration) -> Option<String> {
+        let token = generate_token();
+        let expires_at = Utc::now().timestamp() + ttl.num_seconds();
+        if self.refresh_token_dao.insert_one(family, &hash_token(&token), expires_at).await {
+            Some(token)
+        } else {
+            None
+        }
+    }
+}
+
+/// 256 random bits, hex encoded
+pub fn generate_token() -> String {
+    let bytes: [u8; 32] = rand::thread_rng().gen();
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// opaque tokens are stored as their sha256, a leaked table does not leak usable tokens
+pub fn hash_token(token: &String) -> String {
+    Sha256::digest(token.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// use up a one time token read from its table. used_at is the column as it was read, mark_used
+/// the update setting it only while it is null. two requests reading the token before either used
+/// it both pass the first check, the update lets one of them through
+pub async fn consume_once<F: Future<Output = bool>>(used_at: Option<i64>, mark_used: F) -> bool {
+    used_at.is_none() && mark_used.await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_opaque_tokens() {
+        let token = generate_token();
+        assert_eq!(token.len(), 64);
+        assert_ne!(token, generate_token());
+        assert_eq!(hash_token(&token), hash_token(&token));
+        assert_ne!(hash_token(&token), token);
+    }
+
+    #[actix_rt::test]
+    async fn test_consume_once() {
+        assert!(consume_once(None, async { true }).await);
+        // another request used it between the read and the update
+        assert!(!consume_once(None, async { false }).await);
+        assert!(!consume_once(Some(1), async { panic!("a used token is not updated again") }).await);
+    }
+}
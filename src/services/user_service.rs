@@ -3,15 +3,25 @@ use crate::daos::user_dao::UserDao;
 use sqlx::{MySql, Pool, MySqlPool};
 use sqlx::pool::PoolConnection;
 use crate::entities::user_entity::UserEntity;
+use crate::entities::srp::srp_group::SrpGroup;
+use crate::daos::password_reset_dao::PasswordResetDao;
+use crate::daos::permission_dao::PermissionDao;
+use crate::services::jwt_service::Permission;
+use crate::services::token_service::{generate_token, hash_token};
+use chrono::{Utc, Duration};
 
 pub struct UserService<'a> {
-    user_dao: UserDao<'a>
+    user_dao: UserDao<'a>,
+    password_reset_dao: PasswordResetDao<'a>,
+    permission_dao: PermissionDao<'a>
 }
 
 impl <'a> UserService<'a> {
     pub fn new(conn: &'a MySqlPool) -> Self {
         UserService {
-            user_dao: UserDao::new(conn)
+            user_dao: UserDao::new(conn),
+            password_reset_dao: PasswordResetDao::new(conn),
+            permission_dao: PermissionDao::new(conn)
         }
     }
 
@@ -19,7 +29,37 @@ impl <'a> UserService<'a> {
         self.user_dao.find_by_email(email).await
     }
 
-    pub async fn create_one(&mut self, user_entity: UserEntity) -> Option<UserEntity> {
+    /// permissions embedded in the access tokens of the user
+    pub async fn fetch_permissions(&mut self, user_id: u32) -> Vec<Permission> {
+        self.permission_dao.find_by_user_id(user_id).await
+    }
+
+    /// persist a new password user, the salt and verifier are computed client side
+    pub async fn register_srp_user(&mut self, user_entity: UserEntity) -> Option<UserEntity> {
+        if user_entity.salt.is_none() || user_entity.verifier.is_none() {
+            return None;
+        }
         self.user_dao.insert_one(user_entity).await
     }
-}
\ No newline at end of file
+
+    /// replace salt and verifier, the caller has already proven the user may do so
+    pub async fn change_credentials(&mut self, email: &String, salt: &String, verifier: &String, group: SrpGroup) -> bool {
+        self.user_dao.update_credentials(email, salt, verifier, group).await
+    }
+
+    /// issue a one time password reset token, only its hash is stored
+    pub async fn issue_reset_token(&mut self, user_id: u32, ttl: Duration) -> Option<(String, i64)> {
+        let token = generate_token();
+        let expires_at = Utc::now().timestamp() + ttl.num_seconds();
+        if self.password_reset_dao.insert_one(user_id, &hash_token(&token), expires_at).await {
+            Some((token, expires_at))
+        } else {
+            None
+        }
+    }
+
+    /// use up a reset token of the user, returns false for unknown, expired or used tokens
+    pub async fn consume_reset_token(&mut self, user_id: u32, token: &String) -> bool {
+        self.password_reset_dao.consume(user_id, &hash_token(token), Utc::now().timestamp()).await
+    }
+}